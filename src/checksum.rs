@@ -0,0 +1,177 @@
+use sha2::{Digest, Sha256};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+pub const SUMS_FILE: &str = "SHA256SUMS";
+
+#[derive(Debug, Default, PartialEq)]
+pub struct VerifyReport {
+    pub ok: Vec<String>,
+    // Listed in SHA256SUMS, but not in the destination
+    pub missing: Vec<String>,
+    // In the destination, but not listed in SHA256SUMS
+    pub extra: Vec<String>,
+    // Listed, but the checksum does not match
+    pub corrupted: Vec<String>,
+}
+
+impl VerifyReport {
+    pub fn is_ok(&self) -> bool {
+        self.missing.is_empty() && self.extra.is_empty() && self.corrupted.is_empty()
+    }
+}
+
+pub fn sha256_bytes(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
+}
+
+pub fn sha256_file(path: &Path) -> Result<String, Box<dyn Error>> {
+    Ok(sha256_bytes(&fs::read(path)?))
+}
+
+// File names of all packages in the directory, sorted
+pub fn list_vsix(directory: &str) -> Result<Vec<String>, Box<dyn Error>> {
+    let mut names = Vec::new();
+    for entry in fs::read_dir(directory)? {
+        let entry = entry?;
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if entry.file_type()?.is_file() && file_name.ends_with(".vsix") {
+            names.push(file_name);
+        }
+    }
+    names.sort();
+    Ok(names)
+}
+
+// Same format as the output of `sha256sum`
+fn line(hash: &str, file_name: &str) -> String {
+    format!("{hash}  {file_name}\n")
+}
+
+// Write SHA256SUMS for every package in the destination,
+// and a <file>.sha256 next to each package if requested
+pub fn write_sums(destination: &str, per_file: bool) -> Result<usize, Box<dyn Error>> {
+    let directory = Path::new(destination);
+    let mut sums = String::new();
+    let names = list_vsix(destination)?;
+    for file_name in &names {
+        let hash = sha256_file(&directory.join(file_name))?;
+        sums.push_str(&line(&hash, file_name));
+        if per_file {
+            fs::write(
+                directory.join(format!("{file_name}.sha256")),
+                line(&hash, file_name),
+            )?;
+        }
+    }
+    fs::write(directory.join(SUMS_FILE), sums)?;
+    Ok(names.len())
+}
+
+// Parse "<hash>  <file name>" lines (a leading "*" marks binary mode)
+pub fn parse_sums(content: &str) -> Result<BTreeMap<String, String>, Box<dyn Error>> {
+    let mut sums = BTreeMap::new();
+    for (index, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        let (hash, file_name) = line
+            .split_once(' ')
+            .ok_or_else(|| format!("Invalid line {} in {SUMS_FILE}: {line}", index + 1))?;
+        let file_name = file_name.trim_start_matches(' ').trim_start_matches('*');
+        sums.insert(file_name.to_string(), hash.to_ascii_lowercase());
+    }
+    Ok(sums)
+}
+
+pub fn verify(destination: &str) -> Result<VerifyReport, Box<dyn Error>> {
+    let directory = Path::new(destination);
+    let sums_path = directory.join(SUMS_FILE);
+    let content = fs::read_to_string(&sums_path)
+        .map_err(|e| format!("Failed to read {}: {e}", sums_path.display()))?;
+    let sums = parse_sums(&content)?;
+
+    let mut report = VerifyReport::default();
+    for (file_name, expected) in &sums {
+        let path = directory.join(file_name);
+        if !path.is_file() {
+            report.missing.push(file_name.clone());
+        } else if sha256_file(&path)? == *expected {
+            report.ok.push(file_name.clone());
+        } else {
+            report.corrupted.push(file_name.clone());
+        }
+    }
+    for file_name in list_vsix(destination)? {
+        if !sums.contains_key(&file_name) {
+            report.extra.push(file_name);
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sha256_bytes() {
+        assert_eq!(
+            sha256_bytes(b"abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn test_parse_sums() {
+        let sums = parse_sums("ABC  a.vsix\ndef *b.vsix\n\n").unwrap();
+        assert_eq!(sums.get("a.vsix").map(|s| s.as_str()), Some("abc"));
+        assert_eq!(sums.get("b.vsix").map(|s| s.as_str()), Some("def"));
+        assert!(parse_sums("invalid").is_err());
+    }
+
+    #[test]
+    fn test_write_and_verify() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().to_str().unwrap();
+        fs::write(Path::new(&dir).join("a.vsix"), b"a").unwrap();
+        fs::write(Path::new(&dir).join("b.vsix"), b"b").unwrap();
+        fs::write(Path::new(&dir).join("notes.txt"), b"ignored").unwrap();
+
+        assert_eq!(write_sums(dir, true).unwrap(), 2);
+        let sums = fs::read_to_string(Path::new(&dir).join(SUMS_FILE)).unwrap();
+        assert_eq!(
+            sums,
+            format!(
+                "{}  a.vsix\n{}  b.vsix\n",
+                sha256_bytes(b"a"),
+                sha256_bytes(b"b")
+            )
+        );
+        assert!(Path::new(&dir).join("a.vsix.sha256").exists());
+
+        let report = verify(dir).unwrap();
+        assert!(report.is_ok());
+        assert_eq!(report.ok, vec!["a.vsix", "b.vsix"]);
+    }
+
+    #[test]
+    fn test_verify_problems() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path().to_str().unwrap();
+        fs::write(Path::new(&dir).join("missing.vsix"), b"missing").unwrap();
+        fs::write(Path::new(&dir).join("corrupted.vsix"), b"original").unwrap();
+        write_sums(dir, false).unwrap();
+        fs::remove_file(Path::new(&dir).join("missing.vsix")).unwrap();
+        fs::write(Path::new(&dir).join("corrupted.vsix"), b"modified").unwrap();
+        fs::write(Path::new(&dir).join("extra.vsix"), b"extra").unwrap();
+
+        let report = verify(dir).unwrap();
+        assert!(!report.is_ok());
+        assert_eq!(report.missing, vec!["missing.vsix"]);
+        assert_eq!(report.corrupted, vec!["corrupted.vsix"]);
+        assert_eq!(report.extra, vec!["extra.vsix"]);
+    }
+}
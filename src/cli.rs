@@ -1,4 +1,7 @@
-use clap::{Parser, Subcommand};
+use crate::extensions::openvsx;
+use crate::extensions::url;
+use clap::builder::BoolishValueParser;
+use clap::{Parser, Subcommand, ValueEnum};
 
 #[derive(Parser, Debug)]
 #[clap(author, version, about, long_about = None)]
@@ -10,12 +13,93 @@ pub struct Cli {
     pub download_defaults: DownloadArgs, // Options for default (no subcommand) download
 
     /// Specify proxy url for all commands
-    #[clap(long, global = true)]
+    #[clap(long, global = true, env = "VSIXHARVESTER_PROXY")]
     pub proxy: Option<String>,
 
+    /// Proxy credentials as USER:PASSWORD
+    #[clap(long, global = true, env = "VSIXHARVESTER_PROXY_AUTH", hide_env_values = true, value_name = "USER:PASSWORD")]
+    pub proxy_auth: Option<String>,
+
+    /// Comma separated hosts that bypass --proxy (NO_PROXY is also honoured)
+    #[clap(long, global = true, value_name = "HOSTS", env = "VSIXHARVESTER_NO_PROXY")]
+    pub no_proxy: Option<String>,
+
+    /// PEM file with additional trusted CA certificates (repeatable)
+    #[clap(long, global = true, value_name = "PEM", env = "VSIXHARVESTER_CA_CERT")]
+    pub ca_cert: Vec<String>,
+
+    /// Trust only the --ca-cert certificates, not the trust store of the operating system
+    #[clap(
+        long,
+        global = true,
+        env = "VSIXHARVESTER_NO_SYSTEM_CERTS",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        default_value_t = false,
+        value_parser = BoolishValueParser::new(),
+        value_name = "BOOL",
+    )]
+    pub no_system_certs: bool,
+
+    /// PEM file of the client certificate for mutual TLS
+    #[clap(long, global = true, value_name = "PEM", env = "VSIXHARVESTER_CLIENT_CERT")]
+    pub client_cert: Option<String>,
+
+    /// PEM file of the PKCS#8 private key of --client-cert (if not in the same file)
+    #[clap(long, global = true, value_name = "PEM", env = "VSIXHARVESTER_CLIENT_KEY")]
+    pub client_key: Option<String>,
+
+    /// Token for a private registry as [HOST=]TOKEN (repeatable). Sent as a bearer token,
+    /// or with basic authentication as basic:USER:PASSWORD. Without HOST it is used for
+    /// the hosts of the selected registries.
+    #[clap(long, global = true, env = "VSIXHARVESTER_TOKEN", hide_env_values = true, value_name = "[HOST=]TOKEN")]
+    pub token: Vec<String>,
+
     /// Show verbose infomation for all commands
-    #[clap(short, long, global = true)]
+    #[clap(
+        short,
+        long,
+        global = true,
+        env = "VSIXHARVESTER_VERBOSE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        default_value_t = false,
+        value_parser = BoolishValueParser::new(),
+        value_name = "BOOL",
+    )]
     pub verbose: bool,
+
+    /// Registries to resolve and download extensions from, tried in order
+    #[clap(long, global = true, value_enum, value_delimiter = ',', default_values_t = [RegistryKind::Msft], env = "VSIXHARVESTER_REGISTRY")]
+    pub registry: Vec<RegistryKind>,
+
+    /// Base url of a gallery-compatible marketplace (e.g. an internal mirror)
+    #[clap(long, global = true, env = "VSIXHARVESTER_GALLERY_URL", default_value = url::BASE_URL, value_name = "URL")]
+    pub gallery_url: String,
+
+    /// Template of the package url on the gallery, with {publisher}, {name}, {version} and {platform}
+    #[clap(long, global = true, env = "VSIXHARVESTER_GALLERY_ASSET_URL", value_name = "TEMPLATE")]
+    pub gallery_asset_url: Option<String>,
+
+    /// Base url of the Open VSX registry (for a self-hosted instance)
+    #[clap(long, global = true, default_value = openvsx::DEFAULT_URL, value_name = "URL", env = "VSIXHARVESTER_OPENVSX_URL")]
+    pub openvsx_url: String,
+
+    /// Directory (or file:// url) of VSIX files for the local registry
+    #[clap(long, global = true, value_name = "PATH", env = "VSIXHARVESTER_LOCAL_DIR")]
+    pub local_dir: Option<String>,
+}
+
+#[derive(ValueEnum, Clone, Debug, PartialEq)]
+pub enum RegistryKind {
+    /// Visual Studio Marketplace
+    Msft,
+    /// Open VSX registry
+    Openvsx,
+    /// Directory of previously downloaded VSIX files (see --local-dir)
+    Local,
 }
 
 #[derive(Subcommand, Debug)]
@@ -24,43 +108,300 @@ pub enum Commands {
     Download(DownloadArgs),
     /// Show information for a VSIX extension
     Info(InfoArgs),
+    /// Search the marketplace for extensions
+    Search(SearchArgs),
+    /// Verify downloaded VSIX files against SHA256SUMS
+    Verify(VerifyArgs),
+    /// Serve downloaded VSIX files as a gallery-compatible marketplace
+    Serve(ServeArgs),
+    /// Export downloaded VSIX files as a static mirror
+    Export(ExportArgs),
+    /// Inspect the configuration files (vsixharvester.toml)
+    Config(ConfigArgs),
 }
 
-#[derive(Parser, Debug)]
+#[derive(Parser, Debug, Clone)]
 pub struct DownloadArgs {
     /// Path to extensions.json
-    #[arg(short, long, default_value = "./.vscode/extensions.json")]
+    #[arg(short, long, default_value = "./.vscode/extensions.json", env = "VSIXHARVESTER_INPUT")]
     pub input: String,
 
     /// Output directory
-    #[arg(short, long, default_value = "./.vscode/extensions")]
+    #[arg(short, long, default_value = "./.vscode/extensions", env = "VSIXHARVESTER_DESTINATION")]
     pub destination: String,
 
     /// Force redownload if exists
-    #[arg(long, short = 'f')]
+    #[arg(
+        long,
+        short = 'f',
+        env = "VSIXHARVESTER_FORCE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        default_value_t = false,
+        value_parser = BoolishValueParser::new(),
+        value_name = "BOOL",
+    )]
     pub force: bool,
 
     /// Specify OS architecture for downloaded extensions
-    #[arg(short = 'a', long, value_name = "ARCHITECTURE")] // Changed short name to avoid conflict if -s is used globally
+    #[arg(short = 'a', long, value_name = "ARCHITECTURE", env = "VSIXHARVESTER_ARCH")] // Changed short name to avoid conflict if -s is used globally
     pub arch: Option<String>,
 
     /// Download a single extension by its ID (e.g., publisher.extensionName)
     /// If used, --input is ignored.
     #[arg(short = 's', long, value_name = "EXTENSION_ID")]
     pub single: Option<String>,
+
+    /// Retry interrupted downloads, resuming from the partial file
+    #[arg(long, default_value_t = 3, value_name = "COUNT", env = "VSIXHARVESTER_RETRIES")]
+    pub retries: u32,
+
+    /// Download the marketplace signature (.sigzip) next to each VSIX and verify it
+    #[arg(
+        long,
+        env = "VSIXHARVESTER_VERIFY_SIGNATURE",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        default_value_t = false,
+        value_parser = BoolishValueParser::new(),
+        value_name = "BOOL",
+    )]
+    pub verify_signature: bool,
+
+    /// Also write a <file>.sha256 next to each VSIX (SHA256SUMS is always written)
+    #[arg(
+        long,
+        env = "VSIXHARVESTER_CHECKSUM_FILES",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        default_value_t = false,
+        value_parser = BoolishValueParser::new(),
+        value_name = "BOOL",
+    )]
+    pub checksum_files: bool,
+
+    /// Skip an extension listed in extensions.json (repeatable, case-insensitive)
+    #[arg(long, value_name = "EXTENSION_ID", value_delimiter = ',', env = "VSIXHARVESTER_EXCLUDE")]
+    pub exclude: Vec<String>,
+
+    /// Stop at the first extension that fails instead of continuing with the rest
+    #[arg(
+        long,
+        env = "VSIXHARVESTER_FAIL_FAST",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        default_value_t = false,
+        value_parser = BoolishValueParser::new(),
+        value_name = "BOOL",
+    )]
+    pub fail_fast: bool,
+
+    /// Resolve the extensions and show what would be downloaded, without writing anything
+    #[arg(
+        long,
+        env = "VSIXHARVESTER_DRY_RUN",
+        num_args = 0..=1,
+        require_equals = true,
+        default_missing_value = "true",
+        default_value_t = false,
+        value_parser = BoolishValueParser::new(),
+        value_name = "BOOL",
+    )]
+    pub dry_run: bool,
+
+    /// Write a report of every extension (version, file, size, hash, outcome) to the file
+    #[arg(long, value_name = "PATH", env = "VSIXHARVESTER_REPORT")]
+    pub report: Option<String>,
+
+    /// Format of the --report file
+    #[arg(long, value_enum, default_value_t = ReportFormat::Json, value_name = "FORMAT", env = "VSIXHARVESTER_REPORT_FORMAT")]
+    pub report_format: ReportFormat,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum ReportFormat {
+    /// A JSON document with the totals and one record per extension
+    Json,
+    /// JUnit XML with one test case per extension, for CI dashboards
+    Junit,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum OutputFormat {
+    /// Human readable text
+    Text,
+    /// A JSON array of all extensions
+    Json,
+    /// One JSON object per line, printed as each extension is queried
+    Jsonl,
+}
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum InfoField {
+    /// Registry the information came from
+    Registry,
+    /// Display name
+    Name,
+    /// Short description
+    Description,
+    /// Publisher display name and verification
+    Publisher,
+    Categories,
+    Tags,
+    /// Install (download) count
+    Installs,
+    /// Average rating and number of ratings
+    Rating,
+    /// Date of the last update
+    Updated,
+    /// Source repository
+    Repository,
+    License,
+    /// Latest version per platform
+    Versions,
 }
 
 #[derive(Parser, Debug)]
 pub struct InfoArgs {
     /// Path to extensions.json (used if --single is not provided)
-    #[arg(short, long, default_value = "./.vscode/extensions.json")]
+    #[arg(short, long, default_value = "./.vscode/extensions.json", env = "VSIXHARVESTER_INPUT")]
     pub input: String,
 
     /// Show info for a single extension by its ID (e.g., publisher.extensionName)
     /// If used, --input is ignored.
     #[arg(short = 's', long, value_name = "EXTENSION_ID")]
     pub single: Option<String>,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
+
+    /// List every version with its platforms, publish date, pre-release flag and engine
+    #[arg(long)]
+    pub all_versions: bool,
+
+    /// Show at most this many versions with --all-versions (newest first)
+    #[arg(long, value_name = "N", requires = "all_versions")]
+    pub limit: Option<usize>,
+
+    /// Fields to show, comma separated (all if omitted)
+    #[arg(long, value_enum, value_delimiter = ',', value_name = "FIELDS")]
+    pub fields: Vec<InfoField>,
     // Note: proxy and verbose are global options now, inherited by subcommands.
     // If info-specific arch filtering is needed, add --arch to InfoArgs.
 }
 
+
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum SearchSort {
+    /// Best match first
+    Relevance,
+    /// Most installed first
+    Installs,
+    /// Best rated first
+    Rating,
+    /// Most recently updated first
+    Updated,
+}
+
+#[derive(Parser, Debug)]
+pub struct SearchArgs {
+    /// Text to search for
+    pub text: String,
+
+    /// Page of the results to show, starting at 1
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    pub page: u32,
+
+    /// Number of results per page
+    #[arg(long, default_value_t = 20, value_parser = clap::value_parser!(u32).range(1..=1000))]
+    pub page_size: u32,
+
+    /// Order of the results
+    #[arg(long, value_enum, default_value_t = SearchSort::Relevance)]
+    pub sort: SearchSort,
+
+    /// Only extensions in this category (e.g. "Programming Languages")
+    #[arg(long)]
+    pub category: Option<String>,
+
+    /// Only extensions with this tag (repeatable)
+    #[arg(long, value_name = "TAG")]
+    pub tag: Vec<String>,
+
+    /// Output format (text is a table)
+    #[arg(long, value_enum, default_value_t = OutputFormat::Text)]
+    pub output: OutputFormat,
+
+    /// Add these extensions from the results to extensions.json (repeatable or comma separated)
+    #[arg(long, value_name = "EXTENSION_ID", value_delimiter = ',')]
+    pub add: Vec<String>,
+
+    /// Path to the extensions.json --add writes to
+    #[arg(short, long, default_value = "./.vscode/extensions.json", env = "VSIXHARVESTER_INPUT")]
+    pub input: String,
+}
+
+#[derive(Parser, Debug)]
+pub struct VerifyArgs {
+    /// Directory containing the VSIX files and SHA256SUMS
+    #[arg(short, long, default_value = "./.vscode/extensions", env = "VSIXHARVESTER_DESTINATION")]
+    pub destination: String,
+}
+
+#[derive(Parser, Debug)]
+pub struct ServeArgs {
+    /// Directory containing the VSIX files to serve (the download destination)
+    #[arg(short, long = "root", id = "destination", value_name = "ROOT", default_value = "./.vscode/extensions", env = "VSIXHARVESTER_DESTINATION")]
+    pub root: String,
+
+    /// Port to listen on
+    #[arg(short, long, default_value_t = 8080)]
+    pub port: u16,
+
+    /// Address to listen on
+    #[arg(long, default_value = "127.0.0.1")]
+    pub host: String,
+}
+
+#[derive(ValueEnum, Clone, Debug, PartialEq)]
+pub enum ExportFormat {
+    /// Files at the Open VSX API paths, for any static web server
+    OpenvsxStatic,
+}
+
+#[derive(Parser, Debug)]
+pub struct ExportArgs {
+    /// Directory containing the VSIX files to export (the download destination)
+    #[arg(short, long = "root", id = "destination", value_name = "ROOT", default_value = "./.vscode/extensions", env = "VSIXHARVESTER_DESTINATION")]
+    pub root: String,
+
+    /// Directory to write the mirror to
+    #[arg(short, long)]
+    pub output: String,
+
+    /// Layout of the mirror
+    #[arg(long, value_enum, default_value_t = ExportFormat::OpenvsxStatic)]
+    pub format: ExportFormat,
+
+    /// Url the mirror will be published at, used for links in the metadata
+    /// (root-relative links if omitted)
+    #[arg(long, value_name = "URL", default_value = "")]
+    pub base_url: String,
+}
+
+#[derive(Parser, Debug)]
+pub struct ConfigArgs {
+    #[clap(subcommand)]
+    pub command: ConfigCommands,
+}
+
+#[derive(Subcommand, Debug)]
+pub enum ConfigCommands {
+    /// Print the effective configuration and where each value came from
+    Show,
+}
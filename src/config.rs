@@ -0,0 +1,481 @@
+use crate::cli::Cli;
+use clap::builder::OsStr;
+use clap::parser::ValueSource;
+use clap::{ArgMatches, CommandFactory};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// Searched from the working directory upward
+pub const PROJECT_FILE: &str = "vsixharvester.toml";
+const USER_DIR: &str = "vsixharvester";
+const USER_FILE: &str = "config.toml";
+const REDACTED: &str = "<redacted>";
+
+// A setting of the configuration files. The name is the long flag it provides the default for.
+struct Key {
+    name: &'static str,
+    // Relative paths are resolved against the directory of the configuration file
+    path: bool,
+    // Never printed by `config show`
+    secret: bool,
+    // Subcommands that have the flag too, besides the top-level (download) options.
+    // Global flags are propagated by clap.
+    commands: &'static [&'static str],
+}
+
+impl Key {
+    const fn new(name: &'static str) -> Key {
+        Key {
+            name,
+            path: false,
+            secret: false,
+            commands: &[],
+        }
+    }
+
+    const fn path(self) -> Key {
+        Key { path: true, ..self }
+    }
+
+    const fn secret(self) -> Key {
+        Key {
+            secret: true,
+            ..self
+        }
+    }
+
+    const fn commands(self, commands: &'static [&'static str]) -> Key {
+        Key { commands, ..self }
+    }
+
+    // The clap id of the flag
+    fn id(&self) -> String {
+        self.name.replace('-', "_")
+    }
+}
+
+const KEYS: &[Key] = &[
+    Key::new("input")
+        .path()
+        .commands(&["download", "info", "search"]),
+    // serve and export take it as --root
+    Key::new("destination")
+        .path()
+        .commands(&["download", "verify", "serve", "export"]),
+    Key::new("force").commands(&["download"]),
+    Key::new("arch").commands(&["download"]),
+    Key::new("retries").commands(&["download"]),
+    Key::new("verify-signature").commands(&["download"]),
+    Key::new("checksum-files").commands(&["download"]),
+    Key::new("exclude").commands(&["download"]),
+    Key::new("fail-fast").commands(&["download"]),
+    Key::new("dry-run").commands(&["download"]),
+    Key::new("report").path().commands(&["download"]),
+    Key::new("report-format").commands(&["download"]),
+    Key::new("registry"),
+    Key::new("gallery-url"),
+    Key::new("gallery-asset-url"),
+    Key::new("openvsx-url"),
+    Key::new("local-dir").path(),
+    Key::new("token").secret(),
+    Key::new("proxy"),
+    Key::new("proxy-auth").secret(),
+    Key::new("no-proxy"),
+    Key::new("ca-cert").path(),
+    Key::new("no-system-certs"),
+    Key::new("client-cert").path(),
+    Key::new("client-key").path(),
+    Key::new("verbose"),
+];
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Source {
+    Project(PathBuf),
+    User(PathBuf),
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Project(path) => write!(f, "project {}", path.display()),
+            Source::User(path) => write!(f, "user {}", path.display()),
+        }
+    }
+}
+
+// The merged configuration files: key -> values and the file they came from
+#[derive(Debug, Default)]
+pub struct Config {
+    pub files: Vec<Source>,
+    values: BTreeMap<&'static str, (Vec<String>, Source)>,
+}
+
+fn resolve_path(directory: &Path, value: String) -> String {
+    if value.contains("://") || Path::new(&value).is_absolute() {
+        return value;
+    }
+    directory.join(value).to_string_lossy().to_string()
+}
+
+fn scalar(name: &str, value: &toml::Value) -> Result<String, String> {
+    match value {
+        toml::Value::String(s) => Ok(s.clone()),
+        toml::Value::Integer(i) => Ok(i.to_string()),
+        toml::Value::Boolean(b) => Ok(b.to_string()),
+        _ => Err(format!("Invalid value for {name}")),
+    }
+}
+
+// Read one configuration file
+fn read(path: &Path) -> Result<Vec<(&'static Key, Vec<String>)>, String> {
+    let content =
+        fs::read_to_string(path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+    let table: toml::Table = content
+        .parse()
+        .map_err(|e| format!("Failed to parse {}: {e}", path.display()))?;
+    let directory = path.parent().unwrap_or(Path::new("."));
+    let mut values = Vec::new();
+    for (name, value) in &table {
+        let key = KEYS
+            .iter()
+            .find(|key| key.name == name.as_str())
+            .ok_or_else(|| format!("Unknown key {name} in {}", path.display()))?;
+        let strings = match value {
+            toml::Value::Array(items) => items
+                .iter()
+                .map(|item| scalar(name, item))
+                .collect::<Result<Vec<_>, _>>(),
+            value => scalar(name, value).map(|s| vec![s]),
+        }
+        .map_err(|e| format!("{e} in {}", path.display()))?;
+        let strings = if key.path {
+            strings
+                .into_iter()
+                .map(|s| resolve_path(directory, s))
+                .collect()
+        } else {
+            strings
+        };
+        values.push((key, strings));
+    }
+    Ok(values)
+}
+
+// The nearest vsixharvester.toml in the directory or one of its ancestors
+pub fn project_file(start: &Path) -> Option<PathBuf> {
+    start
+        .ancestors()
+        .map(|directory| directory.join(PROJECT_FILE))
+        .find(|path| path.is_file())
+}
+
+// $XDG_CONFIG_HOME/vsixharvester/config.toml (%APPDATA% on Windows)
+pub fn user_file() -> Option<PathBuf> {
+    let config_home = if cfg!(windows) {
+        std::env::var_os("APPDATA").map(PathBuf::from)
+    } else {
+        std::env::var_os("XDG_CONFIG_HOME")
+            .filter(|s| !s.is_empty())
+            .map(PathBuf::from)
+            .or_else(|| std::env::var_os("HOME").map(|home| PathBuf::from(home).join(".config")))
+    }?;
+    Some(config_home.join(USER_DIR).join(USER_FILE)).filter(|path| path.is_file())
+}
+
+impl Config {
+    // Merge the files, the project file wins over the user file
+    pub fn load(project: Option<PathBuf>, user: Option<PathBuf>) -> Result<Config, String> {
+        let mut config = Config::default();
+        let layers = [project.map(Source::Project), user.map(Source::User)];
+        for source in layers.into_iter().flatten() {
+            let path = match &source {
+                Source::Project(path) | Source::User(path) => path,
+            };
+            for (key, values) in read(path)? {
+                config
+                    .values
+                    .entry(key.name)
+                    .or_insert((values, source.clone()));
+            }
+            config.files.push(source);
+        }
+        Ok(config)
+    }
+
+    pub fn discover() -> Result<Config, String> {
+        let current_dir = std::env::current_dir().map_err(|e| e.to_string())?;
+        Config::load(project_file(&current_dir), user_file())
+    }
+
+    // The command line parser with the configured values as defaults,
+    // so the command line and the environment still take precedence
+    pub fn command(&self) -> clap::Command {
+        let mut command = Cli::command();
+        for key in KEYS {
+            let Some((values, _)) = self.values.get(key.name) else {
+                continue;
+            };
+            let id = key.id();
+            let defaults: Vec<OsStr> = values.iter().map(|s| OsStr::from(s.clone())).collect();
+            command = command.mut_arg(&id, |arg| arg.default_values(defaults.clone()));
+            for name in key.commands {
+                command = command.mut_subcommand(name, |subcommand| {
+                    subcommand.mut_arg(&id, |arg| arg.default_values(defaults.clone()))
+                });
+            }
+        }
+        command
+    }
+
+    // Lines of `config show`: every setting with its effective value and origin
+    pub fn show(&self, matches: &ArgMatches) -> Vec<String> {
+        let mut lines = Vec::new();
+        for key in KEYS {
+            let id = key.id();
+            let values: Vec<String> = matches
+                .get_raw(&id)
+                .map(|raw| raw.map(|s| s.to_string_lossy().to_string()).collect())
+                .unwrap_or_default();
+            let source = match matches.value_source(&id) {
+                Some(ValueSource::CommandLine) => "command line".to_string(),
+                Some(ValueSource::EnvVariable) => {
+                    format!("environment VSIXHARVESTER_{}", id.to_ascii_uppercase())
+                }
+                Some(ValueSource::DefaultValue) => match self.values.get(key.name) {
+                    Some((_, source)) => source.to_string(),
+                    None => "default".to_string(),
+                },
+                _ => "not set".to_string(),
+            };
+            let value = if values.is_empty() {
+                String::new()
+            } else if key.secret {
+                REDACTED.to_string()
+            } else {
+                values.join(",")
+            };
+            if value.is_empty() {
+                lines.push(format!("{}  ({source})", key.name));
+            } else {
+                lines.push(format!("{} = {value}  ({source})", key.name));
+            }
+        }
+        lines
+    }
+}
+
+pub fn show(config: &Config, matches: &ArgMatches) -> Result<(), Box<dyn Error>> {
+    if config.files.is_empty() {
+        println!("# No configuration file found");
+    }
+    for source in &config.files {
+        println!("# {source}");
+    }
+    for line in config.show(matches) {
+        println!("{line}");
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::FromArgMatches;
+
+    fn write_config(dir: &Path, name: &str, content: &str) -> PathBuf {
+        fs::create_dir_all(dir).unwrap();
+        let path = dir.join(name);
+        fs::write(&path, content).unwrap();
+        path
+    }
+
+    fn parse(config: &Config, args: &[&str]) -> (Cli, ArgMatches) {
+        let matches = config
+            .command()
+            .try_get_matches_from(["vsixHarvester"].iter().chain(args))
+            .unwrap();
+        (Cli::from_arg_matches(&matches).unwrap(), matches)
+    }
+
+    #[test]
+    fn test_precedence() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let project = write_config(
+            &dir.join("project"),
+            PROJECT_FILE,
+            r#"
+destination = "vsix"
+arch = "linux-x64"
+exclude = ["ms-python.python"]
+retries = 5
+"#,
+        );
+        let user = write_config(
+            &dir.join("user"),
+            USER_FILE,
+            r#"
+arch = "win32-x64"
+proxy = "http://proxy.example.com:3128"
+registry = ["openvsx", "msft"]
+token = ["secret"]
+"#,
+        );
+        assert_eq!(
+            project_file(&dir.join("project").join("sub")),
+            Some(project.clone())
+        );
+
+        let config = Config::load(Some(project.clone()), Some(user.clone())).unwrap();
+        let (cli, _) = parse(&config, &[]);
+        let download = cli.download_defaults;
+        let destination = dir.join("project").join("vsix");
+        assert_eq!(download.destination, destination.to_str().unwrap());
+        assert_eq!(download.arch.as_deref(), Some("linux-x64"));
+        assert_eq!(download.exclude, vec!["ms-python.python"]);
+        assert_eq!(download.retries, 5);
+        assert_eq!(cli.proxy.as_deref(), Some("http://proxy.example.com:3128"));
+        assert_eq!(
+            cli.registry,
+            vec![
+                crate::cli::RegistryKind::Openvsx,
+                crate::cli::RegistryKind::Msft
+            ]
+        );
+
+        // The command line wins, also for the subcommands
+        let (cli, matches) = parse(&config, &["verify", "--proxy", "http://other:8080"]);
+        assert_eq!(cli.proxy.as_deref(), Some("http://other:8080"));
+        match cli.command {
+            Some(crate::cli::Commands::Verify(args)) => {
+                assert_eq!(args.destination, destination.to_str().unwrap())
+            }
+            command => panic!("unexpected command {command:?}"),
+        }
+        // serve and export read the downloaded packages from the destination
+        for args in [&["serve"][..], &["export", "--output", "mirror"]] {
+            let (cli, _) = parse(&config, args);
+            match cli.command {
+                Some(crate::cli::Commands::Serve(args)) => {
+                    assert_eq!(args.root, destination.to_str().unwrap())
+                }
+                Some(crate::cli::Commands::Export(args)) => {
+                    assert_eq!(args.root, destination.to_str().unwrap())
+                }
+                command => panic!("unexpected command {command:?}"),
+            }
+        }
+
+        let (_, matches_config_show) = parse(&config, &["config", "show"]);
+        let lines = config.show(&matches_config_show);
+        assert!(lines.contains(&format!(
+            "arch = linux-x64  (project {})",
+            project.display()
+        )));
+        assert!(lines.contains(&format!("token = {REDACTED}  (user {})", user.display())));
+        assert!(!lines.iter().any(|line| line.contains("secret")));
+        assert!(config
+            .show(&matches)
+            .contains(&"proxy = http://other:8080  (command line)".to_string()));
+    }
+
+    #[test]
+    fn test_bool_override() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = write_config(
+            dir.path(),
+            PROJECT_FILE,
+            r#"
+verify-signature = true
+checksum-files = true
+fail-fast = true
+no-system-certs = true
+force = true
+dry-run = true
+verbose = true
+"#,
+        );
+        let config = Config::load(Some(project), None).unwrap();
+        let (cli, _) = parse(&config, &[]);
+        let download = cli.download_defaults;
+        assert!(download.verify_signature && download.checksum_files && download.fail_fast);
+        assert!(download.force && download.dry_run);
+        assert!(cli.no_system_certs && cli.verbose);
+
+        // A value from the files can be turned off on the command line
+        let (cli, matches) = parse(
+            &config,
+            &[
+                "download",
+                "--verify-signature=false",
+                "--checksum-files=no",
+                "--fail-fast",
+                "--no-system-certs=false",
+                "--force=false",
+                "--dry-run=0",
+                "--verbose=false",
+            ],
+        );
+        match cli.command {
+            Some(crate::cli::Commands::Download(args)) => {
+                assert!(!args.verify_signature && !args.checksum_files && args.fail_fast);
+                assert!(!args.force && !args.dry_run);
+            }
+            command => panic!("unexpected command {command:?}"),
+        }
+        assert!(!cli.no_system_certs && !cli.verbose);
+        assert!(config
+            .show(&matches)
+            .contains(&"no-system-certs = false  (command line)".to_string()));
+    }
+
+    #[test]
+    fn test_exclude() {
+        let dir = tempfile::tempdir().unwrap();
+        let project = write_config(
+            dir.path(),
+            PROJECT_FILE,
+            "exclude = [\"ms-python.python\", \"ms-vscode.cpptools\"]",
+        );
+        let config = Config::load(Some(project), None).unwrap();
+        let (cli, _) = parse(&config, &[]);
+        assert_eq!(
+            cli.download_defaults.exclude,
+            vec!["ms-python.python", "ms-vscode.cpptools"]
+        );
+
+        // Repeated and comma separated values replace the list of the file
+        let (cli, _) = parse(
+            &config,
+            &[
+                "download",
+                "--exclude",
+                "a.one,b.two",
+                "--exclude",
+                "c.three",
+            ],
+        );
+        match cli.command {
+            Some(crate::cli::Commands::Download(args)) => {
+                assert_eq!(args.exclude, vec!["a.one", "b.two", "c.three"])
+            }
+            command => panic!("unexpected command {command:?}"),
+        }
+    }
+
+    #[test]
+    fn test_invalid_file() {
+        let dir = tempfile::tempdir().unwrap();
+        for content in [
+            "destnation = \"vsix\"",
+            "arch = ",
+            "proxy = { url = \"x\" }",
+        ] {
+            let path = write_config(dir.path(), PROJECT_FILE, content);
+            assert!(Config::load(Some(path), None).is_err(), "{content}");
+        }
+    }
+}
@@ -0,0 +1,170 @@
+use std::fmt;
+
+// Errors of resolving and downloading an extension, with the context needed
+// to report them. One failing extension does not stop the others.
+#[derive(Debug)]
+pub enum Error {
+    // Not in the format publisher.name
+    InvalidId(String),
+    // Not in the format major.minor.patch
+    InvalidVersion(String),
+    // The registry does not know the extension
+    NotFound {
+        id: String,
+        // The registry, e.g. "the marketplace" or the local directory
+        location: String,
+        // Existing extensions with a similar ID, closest first
+        suggestions: Vec<String>,
+    },
+    // The extension exists, but not for the platform
+    PlatformUnavailable {
+        id: String,
+        platform: String,
+    },
+    // No registry could provide the extension, with the reason of each registry
+    Unresolved {
+        id: String,
+        reasons: Vec<(String, Error)>,
+    },
+    // The request failed or the server answered with an error status
+    Http {
+        context: String,
+        message: String,
+    },
+    // A response or a downloaded file could not be decoded
+    Decode {
+        context: String,
+        message: String,
+    },
+    Io {
+        context: String,
+        source: std::io::Error,
+    },
+    // A downloaded file is not the requested package or its signature does not match
+    Validation {
+        context: String,
+        message: String,
+    },
+    // The registry does not support the operation
+    Unsupported(String),
+}
+
+impl Error {
+    pub fn http(context: &str, message: impl fmt::Display) -> Error {
+        Error::Http {
+            context: context.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    pub fn decode(context: &str, message: impl fmt::Display) -> Error {
+        Error::Decode {
+            context: context.to_string(),
+            message: message.to_string(),
+        }
+    }
+
+    pub fn io(context: &str, source: std::io::Error) -> Error {
+        Error::Io {
+            context: context.to_string(),
+            source,
+        }
+    }
+
+    pub fn validation(context: &str, message: impl fmt::Display) -> Error {
+        Error::Validation {
+            context: context.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::InvalidId(id) => {
+                write!(f, "Invalid extension ID {id:?}, expected publisher.name")
+            }
+            Error::InvalidVersion(version) => {
+                write!(f, "Invalid version {version:?}, expected major.minor.patch")
+            }
+            Error::NotFound {
+                id,
+                location,
+                suggestions,
+            } => {
+                write!(f, "{id} does not exist in {location}")?;
+                if !suggestions.is_empty() {
+                    write!(f, ", did you mean {}?", suggestions.join(" or "))?;
+                }
+                Ok(())
+            }
+            Error::PlatformUnavailable { id, platform } => {
+                write!(f, "{id} has no version for {platform}")
+            }
+            Error::Unresolved { id, reasons } => {
+                let reasons: Vec<String> = reasons
+                    .iter()
+                    .map(|(registry, reason)| format!("{registry}: {reason}"))
+                    .collect();
+                write!(f, "No registry provides {id} ({})", reasons.join(", "))
+            }
+            Error::Http { context, message }
+            | Error::Decode { context, message }
+            | Error::Validation { context, message } => write!(f, "{context}: {message}"),
+            Error::Io { context, source } => write!(f, "{context}: {source}"),
+            Error::Unsupported(message) => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        let not_found = Error::NotFound {
+            id: "ms-python.pyhton".to_string(),
+            location: "the marketplace".to_string(),
+            suggestions: vec!["ms-python.python".to_string()],
+        };
+        assert_eq!(
+            not_found.to_string(),
+            "ms-python.pyhton does not exist in the marketplace, did you mean ms-python.python?"
+        );
+        let error = Error::Unresolved {
+            id: "publisher.name".to_string(),
+            reasons: vec![
+                (
+                    "msft".to_string(),
+                    Error::PlatformUnavailable {
+                        id: "publisher.name".to_string(),
+                        platform: "linux-x64".to_string(),
+                    },
+                ),
+                (
+                    "openvsx".to_string(),
+                    Error::http("Failed query for Open VSX API", "500"),
+                ),
+            ],
+        };
+        assert_eq!(
+            error.to_string(),
+            "No registry provides publisher.name (msft: publisher.name has no version for linux-x64, openvsx: Failed query for Open VSX API: 500)"
+        );
+        assert_eq!(
+            Error::InvalidId("rust-analyzer".to_string()).to_string(),
+            "Invalid extension ID \"rust-analyzer\", expected publisher.name"
+        );
+    }
+}
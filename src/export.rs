@@ -0,0 +1,229 @@
+use crate::extensions::local;
+use crate::extensions::local::LocalPackage;
+use crate::extensions::openvsx;
+use crate::extensions::version;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+const UNIVERSAL: &str = "universal";
+// Static servers resolve a directory url to this file (e.g. nginx `index index.json;`)
+pub const INDEX_FILE: &str = "index.json";
+
+fn platform_name(package: &LocalPackage) -> &str {
+    package
+        .manifest
+        .target_platform
+        .as_deref()
+        .unwrap_or(UNIVERSAL)
+}
+
+// Root-relative path of the package, the same one the Open VSX API uses
+fn file_path(package: &LocalPackage) -> String {
+    let manifest = &package.manifest;
+    openvsx::for_download(
+        "",
+        &manifest.publisher,
+        &manifest.name,
+        &manifest.version,
+        manifest.target_platform.clone(),
+    )
+}
+
+fn write_json(output: &Path, api_path: &str, value: &Value) -> Result<(), Box<dyn Error>> {
+    let directory = output.join(api_path.trim_start_matches('/'));
+    fs::create_dir_all(&directory)?;
+    fs::write(
+        directory.join(INDEX_FILE),
+        serde_json::to_string_pretty(value)?,
+    )?;
+    Ok(())
+}
+
+fn copy_file(output: &Path, source: &Path, url_path: &str) -> Result<(), Box<dyn Error>> {
+    let target = output.join(url_path.trim_start_matches('/'));
+    if let Some(parent) = target.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::copy(source, target)?;
+    Ok(())
+}
+
+// Metadata of one version in the shape of /api/{namespace}/{name}/{version}.
+// `downloads` lists every platform the version was published for.
+fn version_json(base_url: &str, package: &LocalPackage, builds: &[&LocalPackage]) -> Value {
+    let manifest = &package.manifest;
+    let mut downloads = Map::new();
+    for build in builds {
+        downloads.insert(
+            platform_name(build).to_string(),
+            json!(format!("{base_url}{}", file_path(build))),
+        );
+    }
+    let mut files = Map::new();
+    let download = format!("{base_url}{}", file_path(package));
+    if local::signature_path(package).is_file() {
+        files.insert(
+            "signature".to_string(),
+            json!(format!("{}.sigzip", download.trim_end_matches(".vsix"))),
+        );
+    }
+    files.insert("download".to_string(), json!(download));
+    json!({
+        "namespace": manifest.publisher,
+        "name": manifest.name,
+        "version": manifest.version,
+        "targetPlatform": platform_name(package),
+        "url": openvsx::extension_url(base_url, &manifest.publisher, &manifest.name),
+        "files": files,
+        "downloads": downloads,
+    })
+}
+
+// Write the packages as a tree of files matching the Open VSX API paths,
+// so that any static web server can act as an Open VSX registry.
+// Returns the number of extensions written.
+pub fn openvsx_static(
+    packages: &[LocalPackage],
+    output: &Path,
+    base_url: &str,
+    verbose: bool,
+) -> Result<usize, Box<dyn Error>> {
+    let base_url = base_url.trim_end_matches('/');
+
+    // namespace.name -> version -> builds for each platform
+    let mut extensions: BTreeMap<String, BTreeMap<String, Vec<&LocalPackage>>> = BTreeMap::new();
+    for package in packages {
+        let manifest = &package.manifest;
+        extensions
+            .entry(format!("{}.{}", manifest.publisher, manifest.name).to_lowercase())
+            .or_default()
+            .entry(manifest.version.clone())
+            .or_default()
+            .push(package);
+    }
+
+    for versions in extensions.values() {
+        let mut ordered: Vec<(&String, &Vec<&LocalPackage>)> = versions.iter().collect();
+        ordered.sort_by(|a, b| version::compare(b.0, a.0));
+        let manifest = &ordered[0].1[0].manifest;
+        let extension_path = openvsx::extension_url("", &manifest.publisher, &manifest.name);
+        if verbose {
+            println!("Export {}.{}", manifest.publisher, manifest.name);
+        }
+
+        let mut version_urls = Map::new();
+        for (index, (version, builds)) in ordered.iter().enumerate() {
+            // Prefer the universal build for the platform independent paths
+            let mut builds = builds.to_vec();
+            builds.sort_by_key(|build| build.manifest.target_platform.clone());
+            for build in &builds {
+                copy_file(output, &build.path, &file_path(build))?;
+                let signature_path = local::signature_path(build);
+                if signature_path.is_file() {
+                    let sigzip = file_path(build);
+                    let sigzip = format!("{}.sigzip", sigzip.trim_end_matches(".vsix"));
+                    copy_file(output, &signature_path, &sigzip)?;
+                }
+                write_json(
+                    output,
+                    &format!("{extension_path}/{}/{version}", platform_name(build)),
+                    &version_json(base_url, build, &builds),
+                )?;
+            }
+            let metadata = version_json(base_url, builds[0], &builds);
+            write_json(output, &format!("{extension_path}/{version}"), &metadata)?;
+            if index == 0 {
+                write_json(output, &extension_path, &metadata)?;
+            }
+            version_urls.insert(
+                version.to_string(),
+                json!(format!("{base_url}{extension_path}/{version}")),
+            );
+        }
+        write_json(
+            output,
+            &format!("{extension_path}/versions"),
+            &json!({
+                "offset": 0,
+                "totalSize": version_urls.len(),
+                "versions": version_urls,
+            }),
+        )?;
+    }
+    Ok(extensions.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extensions::vsix::tests::vsix;
+
+    fn read_index(output: &Path, api_path: &str) -> Value {
+        let content = fs::read_to_string(
+            output
+                .join(api_path.trim_start_matches('/'))
+                .join(INDEX_FILE),
+        )
+        .unwrap();
+        serde_json::from_str(&content).unwrap()
+    }
+
+    #[test]
+    fn test_openvsx_static() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let source = dir.join("source");
+        let output = dir.join("output");
+        fs::create_dir_all(&source).unwrap();
+        for (file_name, version, platform) in [
+            ("a-1.0.0.vsix", "1.0.0", None),
+            ("a-1.1.0.vsix", "1.1.0", None),
+            ("a-1.1.0@linux-x64.vsix", "1.1.0", Some("linux-x64")),
+        ] {
+            fs::write(
+                source.join(file_name),
+                vsix("publisher", "name", version, platform),
+            )
+            .unwrap();
+        }
+        fs::write(source.join("a-1.1.0.sigzip"), b"signature").unwrap();
+        let packages = local::scan(&source, false).unwrap();
+
+        let base_url = "https://mirror.example.com/";
+        assert_eq!(
+            openvsx_static(&packages, &output, base_url, false).unwrap(),
+            1
+        );
+
+        let latest = read_index(&output, "/api/publisher/name");
+        let extension_info = openvsx::parse(&latest).unwrap();
+        assert_eq!(extension_info.arch_versions.len(), 2);
+        assert_eq!(
+            extension_info.arch_versions.get(&None).map(|s| s.as_str()),
+            Some("1.1.0")
+        );
+        assert_eq!(
+            extension_info.arch_signatures.get(&None).map(|s| s.as_str()),
+            Some("https://mirror.example.com/api/publisher/name/1.1.0/file/publisher.name-1.1.0.sigzip")
+        );
+
+        let versions = read_index(&output, "/api/publisher/name/versions");
+        assert_eq!(versions["totalSize"], 2);
+        let old = read_index(&output, "/api/publisher/name/1.0.0");
+        assert_eq!(old["version"], "1.0.0");
+        let platform = read_index(&output, "/api/publisher/name/linux-x64/1.1.0");
+        assert_eq!(platform["targetPlatform"], "linux-x64");
+
+        for platform in [None, Some("linux-x64".to_string())] {
+            let url = openvsx::for_download(base_url, "publisher", "name", "1.1.0", platform);
+            let path = url.strip_prefix(base_url).unwrap();
+            assert!(output.join(path).is_file(), "{path} is missing");
+        }
+        assert!(output
+            .join("api/publisher/name/1.1.0/file/publisher.name-1.1.0.sigzip")
+            .is_file());
+    }
+}
@@ -0,0 +1,149 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use reqwest::header::HeaderValue;
+use std::fmt;
+
+// Prefix of a token sent with basic authentication, e.g. basic:user:password
+const BASIC_PREFIX: &str = "basic:";
+
+// Authorization header for the requests to one host
+#[derive(Clone)]
+struct Credential {
+    host: String,
+    authorization: HeaderValue,
+}
+
+// Tokens scoped per registry host. The values are never printed.
+#[derive(Clone, Default)]
+pub struct Credentials {
+    credentials: Vec<Credential>,
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let hosts: Vec<&str> = self.credentials.iter().map(|c| c.host.as_str()).collect();
+        f.debug_struct("Credentials")
+            .field("hosts", &hosts)
+            .finish()
+    }
+}
+
+fn authorization(token: &str) -> Result<HeaderValue, String> {
+    let value = match token.strip_prefix(BASIC_PREFIX) {
+        Some(user_password) => format!("Basic {}", STANDARD.encode(user_password)),
+        None => format!("Bearer {token}"),
+    };
+    let mut value = HeaderValue::from_str(&value)
+        .map_err(|_| "Token contains characters that are not allowed in a header")?;
+    value.set_sensitive(true);
+    Ok(value)
+}
+
+// A token may end with "=" padding, so only a domain name is taken as a scope
+fn is_host(host: &str) -> bool {
+    (host.contains('.') || host == "localhost")
+        && host
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-')
+}
+
+pub fn host(url: &str) -> Option<String> {
+    reqwest::Url::parse(url)
+        .ok()?
+        .host_str()
+        .map(|host| host.to_ascii_lowercase())
+}
+
+impl Credentials {
+    // Each spec is HOST=TOKEN, or a bare TOKEN for all of the default hosts
+    // (the hosts of the configured registries).
+    // TOKEN is sent as a bearer token, or with basic authentication as basic:USER:PASSWORD.
+    pub fn parse(specs: &[String], default_hosts: &[String]) -> Result<Credentials, String> {
+        let mut credentials = Vec::new();
+        for spec in specs {
+            let (hosts, token) = match spec.split_once('=') {
+                Some((host, token)) if is_host(host) => (vec![host.to_ascii_lowercase()], token),
+                _ => (default_hosts.to_vec(), spec.as_str()),
+            };
+            if token.is_empty() {
+                return Err(format!("Empty token for {}", hosts.join(", ")));
+            }
+            let authorization = authorization(token)
+                .map_err(|e| format!("Invalid token for {}: {e}", hosts.join(", ")))?;
+            for host in hosts {
+                credentials.push(Credential {
+                    host,
+                    authorization: authorization.clone(),
+                });
+            }
+        }
+        Ok(Credentials { credentials })
+    }
+
+    pub fn hosts(&self) -> Vec<&str> {
+        self.credentials.iter().map(|c| c.host.as_str()).collect()
+    }
+
+    // The last token given for the host wins
+    pub fn authorization(&self, url: &str) -> Option<&HeaderValue> {
+        let host = host(url)?;
+        self.credentials
+            .iter()
+            .rev()
+            .find(|credential| credential.host == host)
+            .map(|credential| &credential.authorization)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    #[rstest]
+    #[case("secret", "https://private.example.com/api", Some("Bearer secret"))]
+    #[case(
+        "basic:user:pass",
+        "https://private.example.com/api",
+        Some("Basic dXNlcjpwYXNz")
+    )]
+    #[case("other.example.com=secret", "https://private.example.com/api", None)]
+    #[case(
+        "Private.Example.com=secret",
+        "https://private.example.com/api",
+        Some("Bearer secret")
+    )]
+    #[case("secret", "https://marketplace.visualstudio.com/", None)]
+    #[case(
+        "c2VjcmV0==",
+        "https://private.example.com/api",
+        Some("Bearer c2VjcmV0==")
+    )]
+    fn test_authorization(#[case] spec: &str, #[case] url: &str, #[case] expected: Option<&str>) {
+        let credentials =
+            Credentials::parse(&[spec.to_string()], &["private.example.com".to_string()]).unwrap();
+        assert_eq!(
+            credentials
+                .authorization(url)
+                .map(|value| value.to_str().unwrap()),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_redacted() {
+        let credentials =
+            Credentials::parse(&["private.example.com=secret".to_string()], &[]).unwrap();
+        assert!(!format!("{credentials:?}").contains("secret"));
+        assert!(credentials
+            .authorization("https://private.example.com")
+            .unwrap()
+            .is_sensitive());
+
+        let error = Credentials::parse(&["private.example.com=bad\nsecret".to_string()], &[])
+            .err()
+            .unwrap();
+        assert!(!error.contains("secret"));
+        assert!(Credentials::parse(&["private.example.com=".to_string()], &[]).is_err());
+    }
+}
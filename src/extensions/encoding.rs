@@ -0,0 +1,196 @@
+use flate2::read::{DeflateDecoder, GzDecoder, ZlibDecoder};
+use std::error::Error;
+use std::io::Read;
+
+// Encodings we ask the server for
+pub const ACCEPT: &str = "gzip, deflate, br";
+
+const ZIP_MAGIC: &[u8] = b"PK\x03\x04";
+const GZIP_MAGIC: &[u8] = b"\x1f\x8b";
+
+#[derive(Debug, PartialEq)]
+pub enum Encoding {
+    Identity,
+    Gzip,
+    Deflate,
+    Brotli,
+    Unknown(String),
+}
+
+impl Encoding {
+    pub fn from_token(token: &str) -> Encoding {
+        match token.trim().to_ascii_lowercase().as_str() {
+            "" | "identity" => Encoding::Identity,
+            "gzip" | "x-gzip" => Encoding::Gzip,
+            "deflate" => Encoding::Deflate,
+            "br" => Encoding::Brotli,
+            other => Encoding::Unknown(other.to_string()),
+        }
+    }
+}
+
+// Content-Encoding lists codings in the order they were applied,
+// so they have to be removed in reverse order
+pub fn from_header(content_encoding: Option<&str>) -> Vec<Encoding> {
+    content_encoding
+        .map(|value| value.split(',').rev().map(Encoding::from_token).collect())
+        .unwrap_or_default()
+}
+
+pub fn is_zip(body: &[u8]) -> bool {
+    body.starts_with(ZIP_MAGIC)
+}
+
+// Decode a response body according to its Content-Encoding header.
+// A VSIX is a ZIP archive, so when the header is missing, unknown or does not
+// match the body (e.g. the body was already decompressed on the way), the ZIP
+// magic bytes are used as a last resort.
+pub fn decode(
+    content_encoding: Option<&str>,
+    body: &[u8],
+    verbose: bool,
+) -> Result<Vec<u8>, Box<dyn Error>> {
+    let encodings = from_header(content_encoding);
+    if encodings.is_empty() && body.starts_with(GZIP_MAGIC) {
+        return decode_one(&Encoding::Gzip, body);
+    }
+    let mut decoded = body.to_vec();
+    for encoding in &encodings {
+        decoded = match decode_one(encoding, &decoded) {
+            Ok(decoded) => decoded,
+            Err(e) if is_zip(&decoded) => {
+                if verbose {
+                    println!(
+                        "Content-Encoding {encoding:?} does not match the body, using it as is: {e}"
+                    );
+                }
+                return Ok(decoded);
+            }
+            Err(e) => return Err(e),
+        };
+    }
+    Ok(decoded)
+}
+
+fn decode_one(encoding: &Encoding, body: &[u8]) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut decoded = Vec::new();
+    match encoding {
+        Encoding::Identity => decoded.extend_from_slice(body),
+        Encoding::Gzip => {
+            GzDecoder::new(body).read_to_end(&mut decoded)?;
+        }
+        Encoding::Deflate => {
+            // "deflate" should be zlib wrapped, but some servers send raw deflate
+            if ZlibDecoder::new(body).read_to_end(&mut decoded).is_err() {
+                decoded.clear();
+                DeflateDecoder::new(body).read_to_end(&mut decoded)?;
+            }
+        }
+        Encoding::Brotli => {
+            brotli::Decompressor::new(body, 4096).read_to_end(&mut decoded)?;
+        }
+        Encoding::Unknown(name) => {
+            return Err(Box::from(format!("Unsupported Content-Encoding: {name}")));
+        }
+    }
+    Ok(decoded)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::{DeflateEncoder, GzEncoder, ZlibEncoder};
+    use flate2::Compression;
+    use rstest::rstest;
+    use std::io::Write;
+
+    const VSIX: &[u8] = b"PK\x03\x04 not really a vsix, but starts like one";
+
+    fn gzip(body: &[u8]) -> Vec<u8> {
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(body).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn zlib(body: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(body).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn raw_deflate(body: &[u8]) -> Vec<u8> {
+        let mut encoder = DeflateEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(body).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    fn brotli(body: &[u8]) -> Vec<u8> {
+        let mut compressed = Vec::new();
+        {
+            let mut encoder = brotli::CompressorWriter::new(&mut compressed, 4096, 5, 22);
+            encoder.write_all(body).unwrap();
+        }
+        compressed
+    }
+
+    #[rstest]
+    #[case("gzip", vec![Encoding::Gzip])]
+    #[case("GZIP", vec![Encoding::Gzip])]
+    #[case("identity", vec![Encoding::Identity])]
+    #[case("deflate, br", vec![Encoding::Brotli, Encoding::Deflate])]
+    #[case("zstd", vec![Encoding::Unknown("zstd".to_string())])]
+    fn test_from_header(#[case] header: &str, #[case] expected: Vec<Encoding>) {
+        assert_eq!(from_header(Some(header)), expected);
+    }
+
+    #[test]
+    fn test_decode_gzip() {
+        assert_eq!(decode(Some("gzip"), &gzip(VSIX), false).unwrap(), VSIX);
+    }
+
+    #[test]
+    fn test_decode_deflate() {
+        assert_eq!(decode(Some("deflate"), &zlib(VSIX), false).unwrap(), VSIX);
+        assert_eq!(
+            decode(Some("deflate"), &raw_deflate(VSIX), false).unwrap(),
+            VSIX
+        );
+    }
+
+    #[test]
+    fn test_decode_brotli() {
+        assert_eq!(decode(Some("br"), &brotli(VSIX), false).unwrap(), VSIX);
+    }
+
+    #[test]
+    fn test_decode_identity() {
+        assert_eq!(decode(Some("identity"), VSIX, false).unwrap(), VSIX);
+        assert_eq!(decode(None, VSIX, false).unwrap(), VSIX);
+    }
+
+    #[test]
+    fn test_decode_multiple_encodings() {
+        assert_eq!(
+            decode(Some("gzip, br"), &brotli(&gzip(VSIX)), false).unwrap(),
+            VSIX
+        );
+    }
+
+    #[test]
+    fn test_decode_gzip_without_header() {
+        assert_eq!(decode(None, &gzip(VSIX), false).unwrap(), VSIX);
+    }
+
+    #[test]
+    fn test_decode_already_decompressed() {
+        // The header claims gzip, but the body is the plain archive
+        assert_eq!(decode(Some("gzip"), VSIX, false).unwrap(), VSIX);
+        assert_eq!(decode(Some("zstd"), VSIX, false).unwrap(), VSIX);
+    }
+
+    #[test]
+    fn test_decode_unsupported() {
+        assert!(decode(Some("zstd"), b"not a zip", false).is_err());
+        assert!(decode(Some("gzip"), b"not gzip", false).is_err());
+    }
+}
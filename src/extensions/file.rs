@@ -1,10 +1,14 @@
-use crate::extensions::info;
-use crate::extensions::info::parse_extension_name;
-use crate::extensions::platform;
-use crate::extensions::url;
+use crate::error::Error;
+use crate::extensions::encoding;
+use crate::extensions::info::{parse_extension_name, ExtensionInfo};
+use crate::extensions::registry;
+use crate::extensions::registry::{AssetRequest, Registry};
+use crate::extensions::resume;
+use crate::extensions::signature;
+use crate::extensions::vsix;
+use reqwest::StatusCode;
 use std::fs;
-use std::io::Read; // For GzDecoder
-use flate2::read::GzDecoder; // For GzDecoder
+use std::io::Write;
 use std::path::Path;
 
 fn name(
@@ -21,38 +25,85 @@ fn name(
     }
 }
 
-pub async fn download(
+pub struct DownloadOptions<'a> {
+    // Force redownload if exists
+    pub force: bool,
+    pub verbose: bool,
+    pub os_arch: Option<&'a str>,
+    // Number of retries for an interrupted download
+    pub retries: u32,
+    // Download the .sigzip next to the VSIX and check it against the package
+    pub verify_signature: bool,
+    // Registries in the order they are tried
+    pub registries: &'a [Box<dyn Registry>],
+}
+
+// Where a downloaded (or already existing) package came from
+#[derive(Debug, Clone, PartialEq)]
+pub struct Artifact {
+    pub file_path: String,
+    pub version: String,
+    pub target_platform: Option<String>,
+    // Name of the registry that served the package
+    pub registry: String,
+    // Size of the package in bytes
+    pub size: u64,
+    // The file already existed and was not downloaded again
+    pub skipped: bool,
+}
+
+// What download does with an extension
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Action {
+    Download,
+    // The file already exists
+    Skip,
+    // The file already exists and is downloaded again (--force)
+    Replace,
+}
+
+// The resolved package of an extension and where it goes
+pub struct Plan<'a> {
+    pub publisher: String,
+    pub extension_name: String,
+    pub resolution: registry::Resolution<'a>,
+    pub download_url: String,
+    pub file_name: String,
+    pub file_path: String,
+    pub action: Action,
+}
+
+// Resolve the extension and decide what to do with it, without writing anything
+pub async fn plan<'a>(
     extension: &str,
     destination: &str,
-    force: bool,
-    proxy: Option<&str>,
-    verbose: bool,
-    os_arch: Option<&str>,
-) -> Result<(), Box<dyn std::error::Error>> {
+    options: &DownloadOptions<'a>,
+) -> Result<Plan<'a>, Error> {
+    let DownloadOptions {
+        force,
+        verbose,
+        os_arch,
+        registries,
+        ..
+    } = *options;
     if verbose {
         println!("Progress in extension: {extension}");
     }
 
-    let parsed_extension_name = parse_extension_name(extension);
-    let publisher = &parsed_extension_name.publisher;
-    let extension_name = &parsed_extension_name.name;
+    let parsed_extension_name = parse_extension_name(extension)?;
+    let publisher = parsed_extension_name.publisher;
+    let extension_name = parsed_extension_name.name;
 
-    // Get latest version
-    let extension_info = info::get(publisher, extension_name, proxy, verbose).await?;
-    let versions = &extension_info.arch_versions.clone();
-    if verbose {
-        println!("Latest version of {extension}: {versions:?}");
-    }
+    // Get latest version from the first registry that has one for the platform
+    let resolution =
+        registry::resolve(registries, &publisher, &extension_name, os_arch, verbose).await?;
 
     // Create download url
-    let current = platform::get_current();
-    let target_platform = platform::decide_target(os_arch, current, extension_info.clone());
-    let latest_version = extension_info.arch_versions.get(&target_platform.clone());
-    let download_url = url::for_download(
-        publisher,
-        extension_name,
-        latest_version.clone().unwrap(),
-        target_platform.clone(),
+    let download_url = resolution.registry.download_url(
+        &publisher,
+        &extension_name,
+        &resolution.version,
+        resolution.target_platform.clone(),
     );
     if verbose {
         println!("Download URL: {download_url:?}");
@@ -60,65 +111,687 @@ pub async fn download(
 
     // Make file path
     let file_name = name(
-        target_platform,
-        publisher,
-        extension_name,
-        latest_version.unwrap(),
+        resolution.target_platform.clone(),
+        &publisher,
+        &extension_name,
+        &resolution.version,
     );
     let file_path = format!("{destination}/{file_name}");
+    let action = match (Path::new(&file_path).exists(), force) {
+        (false, _) => Action::Download,
+        (true, false) => Action::Skip,
+        (true, true) => Action::Replace,
+    };
+    Ok(Plan {
+        publisher,
+        extension_name,
+        resolution,
+        download_url,
+        file_name,
+        file_path,
+        action,
+    })
+}
+
+pub async fn download(
+    extension: &str,
+    destination: &str,
+    options: &DownloadOptions<'_>,
+) -> Result<Artifact, Error> {
+    let DownloadOptions {
+        verbose,
+        retries,
+        verify_signature,
+        ..
+    } = *options;
+    let Plan {
+        publisher,
+        extension_name,
+        resolution,
+        download_url,
+        file_name,
+        file_path,
+        action,
+    } = plan(extension, destination, options).await?;
+    let publisher = &publisher;
+    let extension_name = &extension_name;
+    let registry = resolution.registry;
+    let extension_info = &resolution.info;
+    let target_platform = resolution.target_platform.clone();
+    let latest_version = &resolution.version;
+
+    let mut artifact = Artifact {
+        file_path: file_path.clone(),
+        version: latest_version.clone(),
+        target_platform: target_platform.clone(),
+        registry: registry.name().to_string(),
+        size: 0,
+        skipped: false,
+    };
+
+    let signature_path = format!("{destination}/{}", signature::file_name(&file_name));
 
     // Check if the file already exists
-    if !force && Path::new(&file_path).exists() {
+    if action == Action::Skip {
         if verbose {
             println!("Skip download: File is already exists. File Name {file_path}.");
         }
-        return Ok(());
+        artifact.skipped = true;
+        artifact.size = fs::metadata(&file_path).map_or(0, |metadata| metadata.len());
+        if verify_signature {
+            verify_existing(
+                registry,
+                extension_info,
+                &target_platform,
+                &file_path,
+                &signature_path,
+                verbose,
+            )
+            .await
+            .map_err(|e| Error::validation(&format!("Signature of {file_path}"), e))?;
+        }
+        return Ok(artifact);
     }
 
-    // Create http client
-    let client_builder = reqwest::Client::builder();
-    let client = if let Some(proxy_url) = proxy {
-        if verbose {
-            println!("Using proxy: {proxy_url}");
+    // Download VSIX file, resuming from a previous partial download if possible
+    if verbose {
+        println!("Download from {download_url}");
+    }
+    let partial = resume::Partial::new(&file_path);
+    let mut attempt = 0;
+    let meta = loop {
+        attempt += 1;
+        match fetch_to_partial(registry, &download_url, &partial, verbose).await {
+            Ok(meta) => break meta,
+            Err(e) if attempt <= retries => {
+                eprintln!("Download of {extension} interrupted (attempt {attempt}): {e}");
+                tokio::time::sleep(std::time::Duration::from_secs(attempt.into())).await;
+            }
+            Err(e) => {
+                eprintln!("Fail download of {extension}");
+                return Err(Error::http(&format!("Fail download of {download_url}"), e));
+            }
+        }
+    };
+    if verbose {
+        println!("Received encoding: {:?}", meta.content_encoding);
+    }
+    let encoded_bytes = fs::read(&partial.data_path)
+        .map_err(|e| Error::io(&format!("Failed to read {}", partial.data_path), e))?;
+    let vsix_content =
+        match encoding::decode(meta.content_encoding.as_deref(), &encoded_bytes, verbose) {
+            Ok(content) => content,
+            Err(e) => {
+                partial.clear();
+                return Err(Error::decode(
+                    &format!("Downloaded data for {extension}"),
+                    e,
+                ));
+            }
+        };
+
+    // Make sure the package is the requested one before it is saved
+    if let Err(e) = vsix::validate(
+        &vsix_content,
+        publisher,
+        extension_name,
+        latest_version,
+        target_platform.as_deref(),
+    ) {
+        partial.clear();
+        return Err(Error::validation(
+            &format!("Downloaded package {file_name}"),
+            e,
+        ));
+    }
+
+    // Check the signature before anything is saved, so a failed check never
+    // removes or replaces a package that is already in the destination
+    let sigzip = if verify_signature {
+        let result =
+            match fetch_signature(registry, extension_info, &target_platform, verbose).await {
+                Ok(sigzip) => signature::verify(&vsix_content, &sigzip).map(|_| sigzip),
+                Err(e) => Err(e),
+            };
+        match result {
+            Ok(sigzip) => Some(sigzip),
+            Err(e) => {
+                partial.clear();
+                return Err(Error::validation(&format!("Signature of {file_name}"), e));
+            }
         }
-        let proxy = reqwest::Proxy::all(proxy_url)?;
-        client_builder.proxy(proxy).build()?
     } else {
-        client_builder.build()?
+        None
     };
 
-    // Download VSIX file
+    artifact.size = vsix_content.len() as u64;
+    partial
+        .finish(&vsix_content, &file_path)
+        .map_err(|e| Error::io(&format!("Failed to write {file_path}"), e))?;
     if verbose {
-        println!("Download from {download_url}");
+        println!("Saved in {file_path}");
+    }
+    // Only next to a package that was saved, a stale signature would be
+    // trusted for whatever file appears there later
+    if let Some(sigzip) = &sigzip {
+        fs::write(&signature_path, sigzip)
+            .map_err(|e| Error::io(&format!("Failed to write {signature_path}"), e))?;
+        if verbose {
+            println!("Signature verified, saved in {signature_path}");
+        }
+    }
+
+    Ok(artifact)
+}
+
+// Download the signature archive of the package from the marketplace
+async fn fetch_signature(
+    registry: &dyn Registry,
+    extension_info: &ExtensionInfo,
+    target_platform: &Option<String>,
+    verbose: bool,
+) -> Result<Vec<u8>, Box<dyn std::error::Error>> {
+    let signature_url = extension_info
+        .arch_signatures
+        .get(target_platform)
+        .ok_or("The marketplace provides no signature for this package")?;
+    if verbose {
+        println!("Download signature from {signature_url}");
     }
-    let resp = client.get(&download_url)
-        .header(reqwest::header::ACCEPT_ENCODING, "gzip")
-        .send()
+    let asset = registry
+        .fetch(AssetRequest {
+            url: signature_url,
+            resume_from: None,
+            accept_encoding: encoding::ACCEPT,
+        })
         .await?;
-    if !resp.status().is_success() {
-        eprintln!("Fail download of {extension}");
-        return Err(Box::from("Fail download of VSIX"));
+    if !asset.status.is_success() {
+        return Err(Box::from(format!(
+            "Fail download of signature: {}",
+            asset.status
+        )));
     }
-    let compressed_bytes = resp.bytes().await?;
-    let mut decoder = GzDecoder::new(&compressed_bytes[..]);
-    let mut vsix_content = Vec::new();
-    if let Err(e) = decoder.read_to_end(&mut vsix_content) {
-        eprintln!("Failed to decompress gzip data for {extension}: {e}");
-        return Err(Box::new(e));
+    let content_encoding = asset.content_encoding.clone();
+    encoding::decode(content_encoding.as_deref(), &asset.bytes().await?, verbose)
+}
+
+// Check a package that is already in the destination against the signature
+// stored next to it, or against the marketplace's if there is none yet
+async fn verify_existing(
+    registry: &dyn Registry,
+    extension_info: &ExtensionInfo,
+    target_platform: &Option<String>,
+    file_path: &str,
+    signature_path: &str,
+    verbose: bool,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let vsix_content = fs::read(file_path)?;
+    match fs::read(signature_path) {
+        Ok(sigzip) => signature::verify(&vsix_content, &sigzip),
+        Err(_) => {
+            let sigzip =
+                fetch_signature(registry, extension_info, target_platform, verbose).await?;
+            signature::verify(&vsix_content, &sigzip)?;
+            fs::write(signature_path, &sigzip)?;
+            if verbose {
+                println!("Signature verified, saved in {signature_path}");
+            }
+            Ok(())
+        }
     }
+}
 
-    // Save file
-    fs::write(&file_path, &vsix_content)?;
-    if verbose {
-        println!("Saved in {file_path}");
+// Download the raw (still encoded) body into the partial file.
+// If bytes from an earlier attempt exist, request only the remainder with
+// Range/If-Range and append, otherwise (or if the server ignores the range)
+// start over from the beginning.
+async fn fetch_to_partial(
+    registry: &dyn Registry,
+    download_url: &str,
+    partial: &resume::Partial,
+    verbose: bool,
+) -> Result<resume::PartialMeta, Box<dyn std::error::Error>> {
+    let existing = partial.load(download_url);
+    let mut resume_from = None;
+    if let Some((meta, received)) = &existing {
+        if let Some(validator) = meta.validator() {
+            if verbose {
+                println!("Resume download from byte {received}");
+            }
+            resume_from = Some((*received, validator));
+        }
+    }
+    let mut asset = registry
+        .fetch(AssetRequest {
+            url: download_url,
+            resume_from,
+            accept_encoding: encoding::ACCEPT,
+        })
+        .await?;
+    let status = asset.status;
+    if status == StatusCode::RANGE_NOT_SATISFIABLE {
+        partial.clear();
+        return Err(Box::from("Server rejected the resume range"));
+    }
+    if !status.is_success() {
+        return Err(Box::from(format!("Fail download of VSIX: {status}")));
+    }
+
+    // Append only if the server honoured the range for the same representation
+    let resumed = match &existing {
+        Some((meta, received)) if status == StatusCode::PARTIAL_CONTENT => {
+            asset
+                .content_range
+                .as_deref()
+                .and_then(resume::content_range_start)
+                == Some(*received)
+                && meta.content_encoding == asset.content_encoding
+        }
+        _ => false,
+    };
+    if status == StatusCode::PARTIAL_CONTENT && !resumed {
+        partial.clear();
+        return Err(Box::from("Server returned an unexpected range"));
     }
+    let (meta, offset) = match existing {
+        Some((meta, received)) if resumed => (meta, received),
+        _ => {
+            if verbose && existing.is_some() {
+                println!("Server ignored the range request, restart download");
+            }
+            let meta = resume::PartialMeta {
+                url: download_url.to_string(),
+                etag: asset.etag.clone(),
+                last_modified: asset.last_modified.clone(),
+                content_encoding: asset.content_encoding.clone(),
+            };
+            (meta, 0)
+        }
+    };
+    let expected_len = if resumed {
+        asset
+            .content_range
+            .as_deref()
+            .and_then(resume::content_range_total)
+    } else {
+        asset.content_length
+    };
 
-    Ok(())
+    partial.save_meta(&meta)?;
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .write(true)
+        .append(resumed)
+        .truncate(!resumed)
+        .open(&partial.data_path)?;
+    let mut received = offset;
+    while let Some(chunk) = asset.body.chunk().await? {
+        file.write_all(&chunk)?;
+        received += chunk.len() as u64;
+    }
+    file.flush()?;
+
+    if let Some(expected_len) = expected_len {
+        if received != expected_len {
+            return Err(Box::from(format!(
+                "Incomplete download: received {received} of {expected_len} bytes"
+            )));
+        }
+    }
+    Ok(meta)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::extensions::info::ExtensionInfo;
+    use crate::extensions::registry::{Asset, AssetBody};
+    use crate::extensions::signature::tests::sigzip;
+    use crate::extensions::vsix::tests::vsix;
+    use async_trait::async_trait;
+    use std::cell::RefCell;
+    use std::collections::HashMap;
+    use std::error::Error;
+    use std::rc::Rc;
+
+    struct MockBody {
+        chunks: Vec<Vec<u8>>,
+        // Fail after all chunks were sent, like a dropped connection
+        fail: bool,
+    }
+
+    #[async_trait(?Send)]
+    impl AssetBody for MockBody {
+        async fn chunk(&mut self) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+            if !self.chunks.is_empty() {
+                return Ok(Some(self.chunks.remove(0)));
+            }
+            if self.fail {
+                return Err(Box::from("connection reset"));
+            }
+            Ok(None)
+        }
+    }
+
+    struct MockRegistry {
+        content: Vec<u8>,
+        // Drop the connection in the middle of this many first responses
+        interrupted: usize,
+        honour_range: bool,
+        // Provide a valid signature at SIGNATURE_URL
+        signed: bool,
+        requests: Rc<RefCell<Vec<Option<u64>>>>,
+    }
+
+    const SIGNATURE_URL: &str = "mock://signature";
+
+    impl MockRegistry {
+        fn new(content: Vec<u8>, interrupted: usize, honour_range: bool) -> MockRegistry {
+            MockRegistry {
+                content,
+                interrupted,
+                honour_range,
+                signed: false,
+                requests: Rc::new(RefCell::new(Vec::new())),
+            }
+        }
+    }
+
+    #[async_trait(?Send)]
+    impl Registry for MockRegistry {
+        fn name(&self) -> &str {
+            "mock"
+        }
+
+        async fn query(
+            &self,
+            _publisher: &str,
+            _extension_name: &str,
+        ) -> Result<ExtensionInfo, crate::error::Error> {
+            let mut arch_signatures = HashMap::new();
+            if self.signed {
+                arch_signatures.insert(None, SIGNATURE_URL.to_string());
+            }
+            Ok(ExtensionInfo {
+                arch_versions: HashMap::from([(None, "1.0.0".to_string())]),
+                arch_signatures,
+                ..Default::default()
+            })
+        }
+
+        fn download_url(
+            &self,
+            publisher: &str,
+            extension_name: &str,
+            version: &str,
+            _target_platform: Option<String>,
+        ) -> String {
+            format!("mock://{publisher}/{extension_name}/{version}")
+        }
+
+        async fn fetch(&self, request: AssetRequest<'_>) -> Result<Asset, Box<dyn Error>> {
+            if request.url == SIGNATURE_URL {
+                return Ok(Asset {
+                    status: StatusCode::OK,
+                    content_encoding: None,
+                    content_length: None,
+                    content_range: None,
+                    etag: None,
+                    last_modified: None,
+                    body: Box::new(MockBody {
+                        chunks: vec![sigzip(&self.content)],
+                        fail: false,
+                    }),
+                });
+            }
+            let offset = request.resume_from.map(|(offset, _)| offset);
+            self.requests.borrow_mut().push(offset);
+            let len = self.content.len() as u64;
+            let (status, content_range, body) = match offset {
+                Some(offset) if self.honour_range => (
+                    StatusCode::PARTIAL_CONTENT,
+                    Some(format!("bytes {offset}-{}/{len}", len - 1)),
+                    self.content[offset as usize..].to_vec(),
+                ),
+                _ => (StatusCode::OK, None, self.content.clone()),
+            };
+            let interrupt = self.requests.borrow().len() <= self.interrupted;
+            let body = if interrupt {
+                MockBody {
+                    chunks: vec![body[..body.len() / 2].to_vec()],
+                    fail: true,
+                }
+            } else {
+                MockBody {
+                    chunks: vec![body],
+                    fail: false,
+                }
+            };
+            Ok(Asset {
+                status,
+                content_encoding: None,
+                content_length: Some(len - offset.filter(|_| self.honour_range).unwrap_or(0)),
+                content_range,
+                etag: Some("\"v1\"".to_string()),
+                last_modified: None,
+                body: Box::new(body),
+            })
+        }
+    }
+
+    fn options(registries: &[Box<dyn Registry>]) -> DownloadOptions<'_> {
+        DownloadOptions {
+            force: false,
+            verbose: false,
+            os_arch: None,
+            retries: 1,
+            verify_signature: false,
+            registries,
+        }
+    }
+
+    // Download from the mock and return the offsets it was requested with
+    async fn download_with(registry: MockRegistry) -> Vec<Option<u64>> {
+        let temp = tempfile::tempdir().unwrap();
+        let destination = temp.path().to_str().unwrap();
+        let content = registry.content.clone();
+        let requests = registry.requests.clone();
+        let registries: Vec<Box<dyn Registry>> = vec![Box::new(registry)];
+        let artifact = download("publisher.name", destination, &options(&registries))
+            .await
+            .unwrap();
+        let file_path = format!("{destination}/publisher.name-1.0.0.vsix");
+        assert_eq!(artifact.file_path, file_path);
+        assert_eq!(artifact.registry, "mock");
+        assert!(!artifact.skipped);
+        assert_eq!(fs::read(&file_path).unwrap(), content);
+        assert!(!Path::new(&format!("{file_path}.partial")).exists());
+        let requests = requests.borrow().clone();
+        requests
+    }
+
+    #[tokio::test]
+    async fn test_download() {
+        let registry = MockRegistry::new(vsix("publisher", "name", "1.0.0", None), 0, true);
+        assert_eq!(download_with(registry).await, vec![None]);
+    }
+
+    #[tokio::test]
+    async fn test_download_resumes_interrupted() {
+        let content = vsix("publisher", "name", "1.0.0", None);
+        let half = (content.len() / 2) as u64;
+        let registry = MockRegistry::new(content, 1, true);
+        assert_eq!(download_with(registry).await, vec![None, Some(half)]);
+    }
+
+    #[tokio::test]
+    async fn test_download_restarts_if_range_ignored() {
+        let temp = tempfile::tempdir().unwrap();
+        let destination = temp.path().to_str().unwrap();
+        let content = vsix("publisher", "name", "1.0.0", None);
+        let half = content.len() / 2;
+        // The full response sent instead of the range is interrupted too
+        let registry = MockRegistry::new(content.clone(), 2, false);
+        let requests = registry.requests.clone();
+        let registries: Vec<Box<dyn Registry>> = vec![Box::new(registry)];
+        let options = DownloadOptions {
+            retries: 0,
+            ..options(&registries)
+        };
+        let file_path = format!("{destination}/publisher.name-1.0.0.vsix");
+        let partial_path = format!("{file_path}.partial");
+
+        for _ in 0..2 {
+            assert!(download("publisher.name", destination, &options)
+                .await
+                .is_err());
+            // Rewritten from the start, not appended to the earlier half
+            assert_eq!(fs::read(&partial_path).unwrap(), content[..half]);
+        }
+        download("publisher.name", destination, &options)
+            .await
+            .unwrap();
+        assert_eq!(fs::read(&file_path).unwrap(), content);
+        assert!(!Path::new(&partial_path).exists());
+        let half = Some(half as u64);
+        assert_eq!(*requests.borrow(), vec![None, half, half]);
+    }
+
+    #[tokio::test]
+    async fn test_download_rejects_other_package() {
+        let temp = tempfile::tempdir().unwrap();
+        let destination = temp.path().to_str().unwrap();
+        let registries: Vec<Box<dyn Registry>> = vec![Box::new(MockRegistry::new(
+            vsix("publisher", "other", "1.0.0", None),
+            0,
+            true,
+        ))];
+        assert!(
+            download("publisher.name", destination, &options(&registries))
+                .await
+                .is_err()
+        );
+        let file_path = format!("{destination}/publisher.name-1.0.0.vsix");
+        assert!(!Path::new(&file_path).exists());
+        assert!(!Path::new(&format!("{file_path}.partial")).exists());
+
+        // A package that fails validation must not replace an existing file
+        fs::write(&file_path, b"existing").unwrap();
+        let options = DownloadOptions {
+            force: true,
+            ..options(&registries)
+        };
+        assert!(download("publisher.name", destination, &options)
+            .await
+            .is_err());
+        assert_eq!(fs::read(&file_path).unwrap(), b"existing");
+    }
+
+    #[tokio::test]
+    async fn test_verify_signature_keeps_existing() {
+        let temp = tempfile::tempdir().unwrap();
+        let destination = temp.path().to_str().unwrap();
+        let content = vsix("publisher", "name", "1.0.0", None);
+        // The mock registry provides no signature
+        let registries: Vec<Box<dyn Registry>> =
+            vec![Box::new(MockRegistry::new(content.clone(), 0, true))];
+        let file_path = format!("{destination}/publisher.name-1.0.0.vsix");
+        let signature_path = format!("{destination}/publisher.name-1.0.0.sigzip");
+        fs::write(&file_path, &content).unwrap();
+        let options_with = |force| DownloadOptions {
+            force,
+            verify_signature: true,
+            ..options(&registries)
+        };
+
+        // An existing package is checked too, not skipped silently
+        assert!(
+            download("publisher.name", destination, &options_with(false))
+                .await
+                .is_err()
+        );
+        fs::write(&signature_path, sigzip(&content)).unwrap();
+        let artifact = download("publisher.name", destination, &options_with(false))
+            .await
+            .unwrap();
+        assert!(artifact.skipped);
+
+        // A failed check of the replacement must not remove the existing package
+        fs::remove_file(&signature_path).unwrap();
+        assert!(download("publisher.name", destination, &options_with(true))
+            .await
+            .is_err());
+        assert_eq!(fs::read(&file_path).unwrap(), content);
+        assert!(!Path::new(&format!("{file_path}.partial")).exists());
+    }
+
+    #[tokio::test]
+    async fn test_signature_saved_with_package() {
+        let temp = tempfile::tempdir().unwrap();
+        let destination = temp.path().to_str().unwrap();
+        let content = vsix("publisher", "name", "1.0.0", None);
+        let mut registry = MockRegistry::new(content.clone(), 0, true);
+        registry.signed = true;
+        let registries: Vec<Box<dyn Registry>> = vec![Box::new(registry)];
+        let options = DownloadOptions {
+            force: true,
+            verify_signature: true,
+            ..options(&registries)
+        };
+        let file_path = format!("{destination}/publisher.name-1.0.0.vsix");
+        let signature_path = format!("{destination}/publisher.name-1.0.0.sigzip");
+
+        // The package cannot be moved onto a directory of the same name
+        fs::create_dir_all(format!("{file_path}/blocked")).unwrap();
+        assert!(download("publisher.name", destination, &options)
+            .await
+            .is_err());
+        assert!(!Path::new(&signature_path).exists());
+
+        fs::remove_dir_all(&file_path).unwrap();
+        download("publisher.name", destination, &options)
+            .await
+            .unwrap();
+        assert_eq!(fs::read(&file_path).unwrap(), content);
+        assert_eq!(fs::read(&signature_path).unwrap(), sigzip(&content));
+    }
+
+    #[tokio::test]
+    async fn test_plan() {
+        let temp = tempfile::tempdir().unwrap();
+        let destination = temp.path().to_str().unwrap();
+        let registry = MockRegistry::new(Vec::new(), 0, true);
+        let requests = registry.requests.clone();
+        let registries: Vec<Box<dyn Registry>> = vec![Box::new(registry)];
+        let missing = format!("{destination}/missing");
+        let plan = plan("publisher.name", &missing, &options(&registries))
+            .await
+            .unwrap();
+        assert_eq!(plan.action, Action::Download);
+        assert_eq!(plan.download_url, "mock://publisher/name/1.0.0");
+        assert_eq!(
+            plan.file_path,
+            format!("{missing}/publisher.name-1.0.0.vsix")
+        );
+        assert!(!Path::new(&missing).exists());
+
+        fs::write(format!("{destination}/publisher.name-1.0.0.vsix"), b"old").unwrap();
+        let plan_in = |force| {
+            let options = DownloadOptions {
+                force,
+                ..options(&registries)
+            };
+            async move {
+                super::plan("publisher.name", destination, &options)
+                    .await
+                    .unwrap()
+                    .action
+            }
+        };
+        assert_eq!(plan_in(false).await, Action::Skip);
+        assert_eq!(plan_in(true).await, Action::Replace);
+        assert!(requests.borrow().is_empty());
+    }
 
     #[test]
     fn test_name_with_platform() {
@@ -1,5 +1,7 @@
-use crate::extensions::parse::parse;
-use crate::extensions::url::query_url;
+use crate::error::Error;
+use crate::extensions::client::HttpClient;
+use crate::extensions::parse::{is_empty, parse, parse_batch, parse_versions};
+use serde::Serialize;
 use serde_json::json;
 use std::collections::HashMap;
 
@@ -8,6 +10,101 @@ pub struct ExtensionInfo {
     // Key is target platform (None if not exists)
     // Value is the latest version for the platform
     pub arch_versions: HashMap<Option<String>, String>,
+    // Key is target platform (None if not exists)
+    // Value is the url of the signature archive (.sigzip) of the latest version
+    pub arch_signatures: HashMap<Option<String>, String>,
+    pub metadata: Metadata,
+}
+
+// Descriptive fields of the extension, as far as the registry reports them
+#[derive(Serialize, Debug, Clone, PartialEq, Default)]
+pub struct Metadata {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub publisher_display_name: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub publisher_verified: Option<bool>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub categories: Vec<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub install_count: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rating: Option<f64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub rating_count: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub last_updated: Option<String>,
+    // Url of the source repository
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub repository: Option<String>,
+    // License identifier (Open VSX) or url of the license file (marketplace)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+}
+
+// One published version and the platforms it was built for
+#[derive(Serialize, Debug, Clone, PartialEq, Default)]
+pub struct VersionInfo {
+    pub version: String,
+    // Target platforms, "universal" for the platform independent build
+    pub platforms: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub published: Option<String>,
+    pub pre_release: bool,
+    // VS Code version range the version requires, e.g. ^1.80.0
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub engine: Option<String>,
+}
+
+// Add a build to the list of versions, newest first as the registries list them
+pub fn add_build(
+    versions: &mut Vec<VersionInfo>,
+    version: &str,
+    platform: Option<&str>,
+    published: Option<String>,
+    pre_release: bool,
+    engine: Option<String>,
+) {
+    let platform = platform.unwrap_or("universal").to_string();
+    match versions.iter_mut().find(|v| v.version == version) {
+        Some(existing) => {
+            if !existing.platforms.contains(&platform) {
+                existing.platforms.push(platform);
+            }
+            existing.pre_release |= pre_release;
+            if existing.published.is_none() {
+                existing.published = published;
+            }
+            if existing.engine.is_none() {
+                existing.engine = engine;
+            }
+        }
+        None => versions.push(VersionInfo {
+            version: version.to_string(),
+            platforms: vec![platform],
+            published,
+            pre_release,
+            engine,
+        }),
+    }
+}
+
+// Where the marketplace queries report missing extensions
+const LOCATION: &str = "the marketplace";
+
+// The error for an extension the gallery does not know. Similar extensions
+// are only searched for once no registry has it, see `Registry::suggestions`.
+pub fn not_found(id: &str) -> Error {
+    Error::NotFound {
+        id: id.to_string(),
+        location: LOCATION.to_string(),
+        suggestions: Vec::new(),
+    }
 }
 
 #[derive(Debug)]
@@ -18,84 +115,177 @@ pub struct ExtensionName {
 
 pub fn validate_extension_name(name: &str) -> bool {
     let parts: Vec<&str> = name.split('.').collect();
-    parts.len() == 2
+    parts.len() == 2 && parts.iter().all(|part| !part.trim().is_empty())
 }
 
-pub fn parse_extension_name(name: &str) -> ExtensionName {
+pub fn parse_extension_name(name: &str) -> Result<ExtensionName, Error> {
     if !validate_extension_name(name) {
-        eprintln!("Invalid extension format: {name}");
-        panic!("Extension name must be in the format 'publisher.name'");
+        return Err(Error::InvalidId(name.to_string()));
     }
     let parts: Vec<&str> = name.split('.').collect();
     let publisher = parts[0];
     let name = parts[1];
-    ExtensionName {
+    Ok(ExtensionName {
         name: name.to_string(),
         publisher: publisher.to_string(),
+    })
+}
+
+const QUERY_FAILED: &str = "Failed query for Marketplace API";
+const INVALID_RESPONSE: &str = "Invalid response of Marketplace API";
+
+// POST a query to the extensionquery endpoint of the gallery
+pub async fn post_query(
+    client: &HttpClient,
+    query_url: &str,
+    payload: &serde_json::Value,
+) -> Result<serde_json::Value, Error> {
+    let response = client
+        .post(query_url)
+        .header("Content-Type", "application/json")
+        .header("Accept", "application/json;api-version=3.0-preview.1")
+        .header("User-Agent", "Offline VSIX/1.0")
+        .json(payload)
+        .send()
+        .await
+        .map_err(|e| Error::http(QUERY_FAILED, e))?;
+
+    if !response.status().is_success() {
+        return Err(Error::http(QUERY_FAILED, response.status()));
     }
+
+    response
+        .json()
+        .await
+        .map_err(|e| Error::decode(INVALID_RESPONSE, e))
 }
 
 pub async fn get(
+    client: &HttpClient,
+    query_url: &str,
     publisher: &str,
     extension_name: &str,
-    proxy: Option<&str>,
     verbose: bool,
-) -> Result<ExtensionInfo, Box<dyn std::error::Error>> {
+) -> Result<ExtensionInfo, Error> {
     let payload = json!({
         "filters": [{
             "criteria": [
                 {"filterType": 7, "value": format!("{publisher}.{extension_name}")}
             ]
         }],
-        "flags": 914
+        // Files, version properties, asset uri, statistics, categories and tags,
+        // latest version only
+        "flags": 918
     });
 
-    // Create http client
-    let client_builder = reqwest::Client::builder();
-    let client = if let Some(proxy_url) = proxy {
-        if verbose {
-            println!("Using proxy for API request: {}", proxy_url);
-        }
-        let proxy = reqwest::Proxy::all(proxy_url)?;
-        client_builder.proxy(proxy).build()?
-    } else {
-        client_builder.build()?
-    };
-
     // Send POST request
     if verbose {
         println!("Sending query for Marketplace API: {publisher}.{extension_name}");
     }
-    let response = client
-        .post(&query_url())
-        .header("Content-Type", "application/json")
-        .header("Accept", "application/json;api-version=3.0-preview.1")
-        .header("User-Agent", "Offline VSIX/1.0")
-        .json(&payload)
-        .send()
-        .await?;
-
-    if !response.status().is_success() {
-        eprintln!("Failed query for Marketplace API");
-        return Err(Box::from("Failed query for Marketplace API"));
+    let response_json = post_query(client, query_url, &payload).await?;
+    if is_empty(&response_json) {
+        let id = format!("{publisher}.{extension_name}");
+        return Err(not_found(&id));
     }
+    let extension_info = parse(&response_json).map_err(|e| Error::decode(INVALID_RESPONSE, e))?;
 
-    let response_json: serde_json::Value = response.json().await?;
-    let extension_info = parse(&response_json)?;
-    let arch_versions = extension_info.arch_versions;
+    Ok(extension_info)
+}
+
+// Extensions per request of get_many
+pub const BATCH_SIZE: usize = 50;
 
-    Ok(ExtensionInfo { arch_versions })
+// Latest versions of many extensions (publisher.name), BATCH_SIZE per request.
+// The result is keyed by lowercase publisher.name; extensions the gallery
+// does not know are missing from it.
+pub async fn get_many(
+    client: &HttpClient,
+    query_url: &str,
+    ids: &[String],
+    verbose: bool,
+) -> Result<HashMap<String, ExtensionInfo>, Error> {
+    let mut infos = HashMap::new();
+    for chunk in ids.chunks(BATCH_SIZE) {
+        let criteria: Vec<serde_json::Value> = chunk
+            .iter()
+            .map(|id| json!({"filterType": 7, "value": id}))
+            .collect();
+        let payload = json!({
+            "filters": [{
+                "criteria": criteria,
+                "pageNumber": 1,
+                "pageSize": chunk.len()
+            }],
+            // The same as get
+            "flags": 918
+        });
+        if verbose {
+            println!(
+                "Sending query for Marketplace API: {} extensions",
+                chunk.len()
+            );
+        }
+        let response_json = post_query(client, query_url, &payload).await?;
+        infos.extend(parse_batch(&response_json));
+    }
+    Ok(infos)
+}
+
+// Every version of the extension, newest first
+pub async fn versions(
+    client: &HttpClient,
+    query_url: &str,
+    publisher: &str,
+    extension_name: &str,
+    verbose: bool,
+) -> Result<Vec<VersionInfo>, Error> {
+    let payload = json!({
+        "filters": [{
+            "criteria": [
+                {"filterType": 7, "value": format!("{publisher}.{extension_name}")}
+            ]
+        }],
+        // All versions with their properties
+        "flags": 17
+    });
+    if verbose {
+        println!("Sending version query for Marketplace API: {publisher}.{extension_name}");
+    }
+    let response_json = post_query(client, query_url, &payload).await?;
+    parse_versions(&response_json).map_err(|e| Error::decode(INVALID_RESPONSE, e))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::extensions::client;
+    use crate::extensions::url;
+
+    #[test]
+    fn test_parse_extension_name() {
+        let name = parse_extension_name("rust-lang.rust-analyzer").unwrap();
+        assert_eq!(name.publisher, "rust-lang");
+        assert_eq!(name.name, "rust-analyzer");
+        for invalid in ["rust-analyzer", "a.b.c", "publisher.", ".name", ""] {
+            assert!(
+                matches!(parse_extension_name(invalid), Err(Error::InvalidId(id)) if id == invalid),
+                "{invalid}"
+            );
+        }
+    }
 
     #[tokio::test]
     async fn test_get_extension_info() {
-        let extension_info = get("rust-lang", "rust-analyzer", None, false)
-            .await
-            .unwrap();
+        let client = client::build(Default::default(), false).unwrap();
+        let extension_info = get(
+            &client,
+            &url::Gallery::default().query_url(),
+            "rust-lang",
+            "rust-analyzer",
+            false,
+        )
+        .await
+        .unwrap();
 
         // Check if the expected platforms exist
         let expected_archs = vec![
@@ -120,7 +310,7 @@ mod tests {
         for v in extension_info.arch_versions.values() {
             let first = v.chars().next().unwrap();
             assert!(
-                first.is_digit(10),
+                first.is_ascii_digit(),
                 "Version string should start with a digit: {}",
                 v
             );
@@ -0,0 +1,243 @@
+use crate::extensions::info::{add_build, ExtensionInfo, VersionInfo};
+use crate::extensions::signature;
+use crate::extensions::version;
+use crate::extensions::vsix;
+use crate::extensions::vsix::Manifest;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+// A package found in the local directory
+#[derive(Debug, Clone, PartialEq)]
+pub struct LocalPackage {
+    pub manifest: Manifest,
+    pub path: PathBuf,
+}
+
+// Accept both a plain path and a file:// url
+pub fn root_path(location: &str) -> Result<PathBuf, Box<dyn Error>> {
+    let path = if location.starts_with("file://") {
+        reqwest::Url::parse(location)?
+            .to_file_path()
+            .map_err(|_| format!("Invalid file url: {location}"))?
+    } else {
+        PathBuf::from(location)
+    };
+    if !path.is_dir() {
+        return Err(Box::from(format!(
+            "Local registry directory does not exist: {}",
+            path.display()
+        )));
+    }
+    Ok(fs::canonicalize(path)?)
+}
+
+pub fn file_url(path: &Path) -> String {
+    reqwest::Url::from_file_path(path)
+        .map(|url| url.to_string())
+        .unwrap_or_else(|_| format!("file://{}", path.display()))
+}
+
+pub fn url_to_path(url: &str) -> Result<PathBuf, Box<dyn Error>> {
+    reqwest::Url::parse(url)?
+        .to_file_path()
+        .map_err(|_| Box::from(format!("Not a local file url: {url}")))
+}
+
+// Find every package under the directory (including subdirectories)
+// and read its manifest. Files that are not valid packages are skipped.
+pub fn scan(root: &Path, verbose: bool) -> Result<Vec<LocalPackage>, Box<dyn Error>> {
+    let mut packages = Vec::new();
+    let mut directories = vec![root.to_path_buf()];
+    while let Some(directory) = directories.pop() {
+        for entry in fs::read_dir(&directory)? {
+            let path = entry?.path();
+            if path.is_dir() {
+                directories.push(path);
+                continue;
+            }
+            if path.extension().and_then(|e| e.to_str()) != Some("vsix") {
+                continue;
+            }
+            let manifest = fs::read(&path)
+                .map_err(Box::from)
+                .and_then(|content| vsix::read_manifest(&content));
+            match manifest {
+                Ok(manifest) => packages.push(LocalPackage { manifest, path }),
+                Err(e) => eprintln!("Skip {}: {e}", path.display()),
+            }
+        }
+    }
+    packages.sort_by(|a, b| a.path.cmp(&b.path));
+    if verbose {
+        println!("Found {} packages in {}", packages.len(), root.display());
+    }
+    Ok(packages)
+}
+
+fn is_extension(manifest: &Manifest, publisher: &str, extension_name: &str) -> bool {
+    manifest.publisher.eq_ignore_ascii_case(publisher)
+        && manifest.name.eq_ignore_ascii_case(extension_name)
+}
+
+// Where the .sigzip of the package is, if it was downloaded with --verify-signature
+pub fn signature_path(package: &LocalPackage) -> PathBuf {
+    let file_name = package
+        .path
+        .file_name()
+        .unwrap_or_default()
+        .to_string_lossy();
+    package
+        .path
+        .with_file_name(signature::file_name(&file_name))
+}
+
+// Latest version per platform, as a registry would report it
+pub fn info(packages: &[LocalPackage], publisher: &str, extension_name: &str) -> ExtensionInfo {
+    let mut latest: HashMap<Option<String>, &LocalPackage> = HashMap::new();
+    for package in packages {
+        if !is_extension(&package.manifest, publisher, extension_name) {
+            continue;
+        }
+        let platform = package.manifest.target_platform.clone();
+        let newer = latest.get(&platform).is_none_or(|current| {
+            version::compare(&package.manifest.version, &current.manifest.version)
+                == Ordering::Greater
+        });
+        if newer {
+            latest.insert(platform, package);
+        }
+    }
+
+    let mut extension_info = ExtensionInfo::default();
+    for (platform, package) in latest {
+        let signature_path = signature_path(package);
+        if signature_path.is_file() {
+            extension_info
+                .arch_signatures
+                .insert(platform.clone(), file_url(&signature_path));
+        }
+        extension_info
+            .arch_versions
+            .insert(platform, package.manifest.version.clone());
+    }
+    extension_info
+}
+
+// Every version in the directory, newest first
+pub fn versions(
+    packages: &[LocalPackage],
+    publisher: &str,
+    extension_name: &str,
+) -> Vec<VersionInfo> {
+    let mut matching: Vec<&LocalPackage> = packages
+        .iter()
+        .filter(|package| is_extension(&package.manifest, publisher, extension_name))
+        .collect();
+    matching.sort_by(|a, b| version::compare(&b.manifest.version, &a.manifest.version));
+    let mut versions = Vec::new();
+    for package in matching {
+        let manifest = &package.manifest;
+        add_build(
+            &mut versions,
+            &manifest.version,
+            manifest.target_platform.as_deref(),
+            None,
+            manifest.pre_release,
+            manifest.engine.clone(),
+        );
+    }
+    versions
+}
+
+pub fn find<'a>(
+    packages: &'a [LocalPackage],
+    publisher: &str,
+    extension_name: &str,
+    version: &str,
+    target_platform: Option<&str>,
+) -> Option<&'a LocalPackage> {
+    packages.iter().find(|package| {
+        is_extension(&package.manifest, publisher, extension_name)
+            && package.manifest.version == version
+            && package.manifest.target_platform.as_deref() == target_platform
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extensions::vsix::tests::vsix;
+
+    #[test]
+    fn test_scan_and_info() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        fs::create_dir_all(dir.join("old")).unwrap();
+        fs::write(
+            dir.join("old/a.vsix"),
+            vsix("Publisher", "name", "1.9.0", None),
+        )
+        .unwrap();
+        fs::write(
+            dir.join("b.vsix"),
+            vsix("publisher", "name", "1.10.0", None),
+        )
+        .unwrap();
+        fs::write(dir.join("b.sigzip"), b"signature").unwrap();
+        fs::write(
+            dir.join("c.vsix"),
+            vsix("publisher", "name", "1.0.0", Some("linux-x64")),
+        )
+        .unwrap();
+        fs::write(
+            dir.join("d.vsix"),
+            vsix("publisher", "other", "2.0.0", None),
+        )
+        .unwrap();
+        fs::write(dir.join("broken.vsix"), b"not a zip").unwrap();
+        fs::write(dir.join("notes.txt"), b"ignored").unwrap();
+
+        let root = root_path(&file_url(dir)).unwrap();
+        let packages = scan(&root, false).unwrap();
+        assert_eq!(packages.len(), 4);
+
+        let extension_info = info(&packages, "publisher", "name");
+        assert_eq!(
+            extension_info.arch_versions,
+            HashMap::from([
+                (None, "1.10.0".to_string()),
+                (Some("linux-x64".to_string()), "1.0.0".to_string()),
+            ])
+        );
+        assert_eq!(
+            extension_info.arch_signatures.get(&None),
+            Some(&file_url(&root.join("b.sigzip")))
+        );
+
+        let versions: Vec<(String, Vec<String>)> = versions(&packages, "publisher", "name")
+            .into_iter()
+            .map(|v| (v.version, v.platforms))
+            .collect();
+        assert_eq!(
+            versions,
+            vec![
+                ("1.10.0".to_string(), vec!["universal".to_string()]),
+                ("1.9.0".to_string(), vec!["universal".to_string()]),
+                ("1.0.0".to_string(), vec!["linux-x64".to_string()]),
+            ]
+        );
+
+        let package = find(&packages, "publisher", "name", "1.10.0", None).unwrap();
+        assert_eq!(package.path, root.join("b.vsix"));
+        assert_eq!(url_to_path(&file_url(&package.path)).unwrap(), package.path);
+        assert!(find(&packages, "publisher", "name", "1.10.0", Some("linux-x64")).is_none());
+    }
+
+    #[test]
+    fn test_root_path_missing() {
+        assert!(root_path("/nonexistent/vsixharvester").is_err());
+    }
+}
@@ -1,6 +1,16 @@
+pub mod client;
+pub mod encoding;
 pub mod file;
 pub mod platform;
 pub mod info;
+pub mod openvsx;
 pub mod url;
 pub mod parse;
-pub mod version;
\ No newline at end of file
+pub mod registry;
+pub mod resume;
+pub mod signature;
+pub mod version;
+pub mod vsix;
+pub mod local;
+pub mod auth;
+pub mod search;
@@ -0,0 +1,344 @@
+use crate::error::Error;
+use crate::extensions::client::HttpClient;
+use crate::extensions::info::{add_build, ExtensionInfo, Metadata, VersionInfo};
+use crate::extensions::version;
+use std::collections::HashMap;
+
+pub const DEFAULT_URL: &str = "https://open-vsx.org";
+
+// Open VSX calls the platform independent build "universal"
+const UNIVERSAL: &str = "universal";
+const QUERY_FAILED: &str = "Failed query for Open VSX API";
+const INVALID_RESPONSE: &str = "Invalid response of Open VSX API";
+
+pub fn extension_url(base_url: &str, namespace: &str, extension_name: &str) -> String {
+    let base_url = base_url.trim_end_matches('/');
+    format!("{base_url}/api/{namespace}/{extension_name}")
+}
+
+pub fn for_download(
+    base_url: &str,
+    namespace: &str,
+    extension_name: &str,
+    version: &str,
+    target_platform: Option<String>,
+) -> String {
+    let extension_url = extension_url(base_url, namespace, extension_name);
+    match target_platform {
+        Some(platform) => format!(
+            "{extension_url}/{platform}/{version}/file/{namespace}.{extension_name}-{version}@{platform}.vsix"
+        ),
+        None => {
+            format!("{extension_url}/{version}/file/{namespace}.{extension_name}-{version}.vsix")
+        }
+    }
+}
+
+fn to_platform(platform: &str) -> Option<String> {
+    if platform == UNIVERSAL {
+        None
+    } else {
+        Some(platform.to_string())
+    }
+}
+
+// Restructure the response of /api/{namespace}/{name} into ExtensionInfo.
+// "downloads" lists every platform the latest version was published for.
+pub fn parse(response_json: &serde_json::Value) -> Result<ExtensionInfo, String> {
+    if let Some(error) = response_json["error"].as_str() {
+        return Err(error.to_string());
+    }
+    let version = response_json["version"]
+        .as_str()
+        .ok_or("Failed to get version")?;
+
+    let mut arch_versions = HashMap::new();
+    if let Some(downloads) = response_json["downloads"].as_object() {
+        for platform in downloads.keys() {
+            arch_versions.insert(to_platform(platform), version.to_string());
+        }
+    }
+    let target_platform = response_json["targetPlatform"]
+        .as_str()
+        .unwrap_or(UNIVERSAL);
+    arch_versions
+        .entry(to_platform(target_platform))
+        .or_insert_with(|| version.to_string());
+
+    let mut arch_signatures = HashMap::new();
+    if let Some(signature_url) = response_json["files"]["signature"].as_str() {
+        arch_signatures.insert(to_platform(target_platform), signature_url.to_string());
+    }
+    Ok(ExtensionInfo {
+        arch_versions,
+        arch_signatures,
+        metadata: metadata(response_json),
+    })
+}
+
+fn metadata(response_json: &serde_json::Value) -> Metadata {
+    let string = |key: &str| {
+        response_json[key]
+            .as_str()
+            .filter(|s| !s.is_empty())
+            .map(|s| s.to_string())
+    };
+    let strings = |key: &str| -> Vec<String> {
+        response_json[key]
+            .as_array()
+            .map(|items| {
+                items
+                    .iter()
+                    .filter_map(|item| item.as_str().map(|s| s.to_string()))
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    Metadata {
+        display_name: string("displayName"),
+        description: string("description"),
+        publisher_display_name: string("namespaceDisplayName"),
+        publisher_verified: response_json["verified"].as_bool(),
+        categories: strings("categories"),
+        tags: strings("tags"),
+        install_count: response_json["downloadCount"].as_u64(),
+        rating: response_json["averageRating"].as_f64(),
+        rating_count: response_json["reviewCount"].as_u64(),
+        last_updated: string("timestamp"),
+        repository: string("repository"),
+        license: string("license"),
+    }
+}
+
+// The error message of Open VSX for a failed request
+fn check_status(
+    status: reqwest::StatusCode,
+    response_json: &serde_json::Value,
+) -> Result<(), Error> {
+    if status.is_success() {
+        return Ok(());
+    }
+    let message = response_json["error"].as_str().unwrap_or_default();
+    Err(Error::http(QUERY_FAILED, format!("{status} {message}")))
+}
+
+pub async fn get(
+    client: &HttpClient,
+    base_url: &str,
+    namespace: &str,
+    extension_name: &str,
+    verbose: bool,
+) -> Result<ExtensionInfo, Error> {
+    let url = extension_url(base_url, namespace, extension_name);
+    if verbose {
+        println!("Sending query for Open VSX API: {url}");
+    }
+    let response = client
+        .get(&url)
+        .header("Accept", "application/json")
+        .header("User-Agent", "Offline VSIX/1.0")
+        .send()
+        .await
+        .map_err(|e| Error::http(QUERY_FAILED, e))?;
+
+    let status = response.status();
+    let response_json: serde_json::Value = response.json().await.unwrap_or_default();
+    if status == reqwest::StatusCode::NOT_FOUND {
+        return Err(Error::NotFound {
+            id: format!("{namespace}.{extension_name}"),
+            location: base_url.trim_end_matches('/').to_string(),
+            suggestions: Vec::new(),
+        });
+    }
+    check_status(status, &response_json)?;
+    parse(&response_json).map_err(|e| Error::decode(INVALID_RESPONSE, e))
+}
+
+// Restructure the response of /api/-/query with all versions, newest first
+pub fn parse_versions(response_json: &serde_json::Value) -> Result<Vec<VersionInfo>, String> {
+    if let Some(error) = response_json["error"].as_str() {
+        return Err(error.to_string());
+    }
+    let extensions = response_json["extensions"]
+        .as_array()
+        .ok_or("Failed to get extensions array")?;
+    let mut versions = Vec::new();
+    for extension in extensions {
+        if let Some(version) = extension["version"].as_str() {
+            add_build(
+                &mut versions,
+                version,
+                extension["targetPlatform"].as_str(),
+                extension["timestamp"].as_str().map(|s| s.to_string()),
+                extension["preRelease"].as_bool().unwrap_or(false),
+                extension["engines"]["vscode"]
+                    .as_str()
+                    .map(|s| s.to_string()),
+            );
+        }
+    }
+    versions.sort_by(|a, b| version::compare(&b.version, &a.version));
+    Ok(versions)
+}
+
+pub async fn versions(
+    client: &HttpClient,
+    base_url: &str,
+    namespace: &str,
+    extension_name: &str,
+    verbose: bool,
+) -> Result<Vec<VersionInfo>, Error> {
+    let url = format!("{}/api/-/query", base_url.trim_end_matches('/'));
+    if verbose {
+        println!("Sending version query for Open VSX API: {namespace}.{extension_name}");
+    }
+    let response = client
+        .get(&url)
+        .query(&[
+            ("namespaceName", namespace),
+            ("extensionName", extension_name),
+            ("includeAllVersions", "true"),
+        ])
+        .header("Accept", "application/json")
+        .header("User-Agent", "Offline VSIX/1.0")
+        .send()
+        .await
+        .map_err(|e| Error::http(QUERY_FAILED, e))?;
+
+    let status = response.status();
+    let response_json: serde_json::Value = response.json().await.unwrap_or_default();
+    check_status(status, &response_json)?;
+    parse_versions(&response_json).map_err(|e| Error::decode(INVALID_RESPONSE, e))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const MULTI_PLATFORM: &str = r#"
+{
+    "namespace": "rust-lang",
+    "name": "rust-analyzer",
+    "version": "0.4.2304",
+    "targetPlatform": "linux-x64",
+    "files": {
+        "download": "https://open-vsx.org/api/rust-lang/rust-analyzer/linux-x64/0.4.2304/file/rust-lang.rust-analyzer-0.4.2304@linux-x64.vsix",
+        "signature": "https://open-vsx.org/api/rust-lang/rust-analyzer/linux-x64/0.4.2304/file/rust-lang.rust-analyzer-0.4.2304@linux-x64.sigzip"
+    },
+    "downloads": {
+        "linux-x64": "https://open-vsx.org/api/rust-lang/rust-analyzer/linux-x64/0.4.2304/file/rust-lang.rust-analyzer-0.4.2304@linux-x64.vsix",
+        "win32-x64": "https://open-vsx.org/api/rust-lang/rust-analyzer/win32-x64/0.4.2304/file/rust-lang.rust-analyzer-0.4.2304@win32-x64.vsix"
+    }
+}
+"#;
+
+    const UNIVERSAL_ONLY: &str = r#"
+{
+    "namespace": "redhat",
+    "name": "vscode-yaml",
+    "version": "1.15.0",
+    "targetPlatform": "universal",
+    "displayName": "YAML",
+    "description": "YAML Language Support by Red Hat",
+    "namespaceDisplayName": "Red Hat",
+    "verified": true,
+    "categories": ["Programming Languages"],
+    "tags": ["yaml"],
+    "downloadCount": 1500000,
+    "averageRating": 4.5,
+    "reviewCount": 12,
+    "timestamp": "2024-05-01T12:00:00.000000Z",
+    "repository": "https://github.com/redhat-developer/vscode-yaml",
+    "license": "MIT",
+    "downloads": {
+        "universal": "https://open-vsx.org/api/redhat/vscode-yaml/1.15.0/file/redhat.vscode-yaml-1.15.0.vsix"
+    }
+}
+"#;
+
+    #[test]
+    fn test_parse_multi_platform() {
+        let response_json: serde_json::Value = serde_json::from_str(MULTI_PLATFORM).unwrap();
+        let extension_info = parse(&response_json).unwrap();
+        assert_eq!(extension_info.arch_versions.len(), 2);
+        for platform in ["linux-x64", "win32-x64"] {
+            assert_eq!(
+                extension_info
+                    .arch_versions
+                    .get(&Some(platform.to_string()))
+                    .map(|s| s.as_str()),
+                Some("0.4.2304")
+            );
+        }
+        assert!(extension_info
+            .arch_signatures
+            .contains_key(&Some("linux-x64".to_string())));
+    }
+
+    #[test]
+    fn test_parse_universal() {
+        let response_json: serde_json::Value = serde_json::from_str(UNIVERSAL_ONLY).unwrap();
+        let extension_info = parse(&response_json).unwrap();
+        assert_eq!(
+            extension_info.arch_versions.get(&None).map(|s| s.as_str()),
+            Some("1.15.0")
+        );
+        assert_eq!(extension_info.arch_versions.len(), 1);
+
+        let metadata = extension_info.metadata;
+        assert_eq!(metadata.display_name.as_deref(), Some("YAML"));
+        assert_eq!(metadata.publisher_display_name.as_deref(), Some("Red Hat"));
+        assert_eq!(metadata.publisher_verified, Some(true));
+        assert_eq!(metadata.categories, vec!["Programming Languages"]);
+        assert_eq!(metadata.install_count, Some(1500000));
+        assert_eq!(metadata.rating, Some(4.5));
+        assert_eq!(metadata.rating_count, Some(12));
+        assert_eq!(metadata.license.as_deref(), Some("MIT"));
+    }
+
+    #[test]
+    fn test_parse_versions() {
+        let response_json = serde_json::json!({
+            "extensions": [
+                {"version": "1.9.0", "targetPlatform": "universal", "timestamp": "2023-01-01T00:00:00Z", "engines": {"vscode": "^1.70.0"}},
+                {"version": "1.10.0", "targetPlatform": "linux-x64", "preRelease": true},
+                {"version": "1.10.0", "targetPlatform": "win32-x64", "preRelease": true}
+            ]
+        });
+        let versions = parse_versions(&response_json).unwrap();
+        assert_eq!(versions.len(), 2);
+        assert_eq!(versions[0].version, "1.10.0");
+        assert_eq!(versions[0].platforms, vec!["linux-x64", "win32-x64"]);
+        assert!(versions[0].pre_release);
+        assert_eq!(versions[1].platforms, vec!["universal"]);
+        assert_eq!(versions[1].engine.as_deref(), Some("^1.70.0"));
+        assert!(parse_versions(&serde_json::json!({"error": "Not found"})).is_err());
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let response_json = serde_json::json!({"error": "Extension not found: foo.bar"});
+        assert_eq!(
+            parse(&response_json),
+            Err("Extension not found: foo.bar".to_string())
+        );
+    }
+
+    #[test]
+    fn test_for_download() {
+        assert_eq!(
+            for_download(DEFAULT_URL, "redhat", "vscode-yaml", "1.15.0", None),
+            "https://open-vsx.org/api/redhat/vscode-yaml/1.15.0/file/redhat.vscode-yaml-1.15.0.vsix"
+        );
+        assert_eq!(
+            for_download(
+                "https://openvsx.example.com/",
+                "rust-lang",
+                "rust-analyzer",
+                "0.4.2304",
+                Some("linux-x64".to_string())
+            ),
+            "https://openvsx.example.com/api/rust-lang/rust-analyzer/linux-x64/0.4.2304/file/rust-lang.rust-analyzer-0.4.2304@linux-x64.vsix"
+        );
+    }
+}
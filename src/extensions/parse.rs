@@ -1,26 +1,159 @@
-use crate::extensions::info::ExtensionInfo;
+use crate::extensions::info::{add_build, ExtensionInfo, Metadata, VersionInfo};
+use crate::extensions::signature;
 use std::collections::HashMap;
 
+// The query matched no extension, e.g. an unknown or misspelled ID
+pub fn is_empty(response_json: &serde_json::Value) -> bool {
+    response_json["results"][0]["extensions"]
+        .as_array()
+        .is_some_and(|extensions| extensions.is_empty())
+}
+
 pub fn parse(response_json: &serde_json::Value) -> Result<ExtensionInfo, &'static str> {
-    let versions_array = response_json["results"][0]["extensions"][0]["versions"]
+    parse_extension(&response_json["results"][0]["extensions"][0])
+}
+
+// Every extension in the response of a batch query, by lowercase publisher.name
+pub fn parse_batch(response_json: &serde_json::Value) -> HashMap<String, ExtensionInfo> {
+    let mut infos = HashMap::new();
+    let extensions = response_json["results"][0]["extensions"].as_array();
+    for extension in extensions.into_iter().flatten() {
+        let (Some(publisher), Some(name)) = (
+            extension["publisher"]["publisherName"].as_str(),
+            extension["extensionName"].as_str(),
+        ) else {
+            continue;
+        };
+        if let Ok(info) = parse_extension(extension) {
+            infos.insert(format!("{publisher}.{name}").to_lowercase(), info);
+        }
+    }
+    infos
+}
+
+pub fn parse_extension(extension: &serde_json::Value) -> Result<ExtensionInfo, &'static str> {
+    let versions_array = extension["versions"]
         .as_array()
         .ok_or("Failed to get versions array")?;
 
     // Restrucuturing the versions array into a dictionary
     // To be each architecuture has the latest version
     let mut arch_versions: HashMap<Option<String>, String> = HashMap::new();
+    let mut arch_signatures: HashMap<Option<String>, String> = HashMap::new();
     for v in versions_array {
         if let Some(version_str) = v["version"].as_str() {
             let arch = v
                 .get("targetPlatform")
                 .and_then(|v| v.as_str())
                 .map(|s| s.to_string());
-            if !arch_versions.contains_key(&arch) {
-                arch_versions.insert(arch, version_str.to_string());
+            if arch_versions.contains_key(&arch) {
+                continue;
+            }
+            if let Some(signature_url) = asset_source(v, signature::ASSET_TYPE) {
+                arch_signatures.insert(arch.clone(), signature_url);
             }
+            arch_versions.insert(arch, version_str.to_string());
+        }
+    }
+    Ok(ExtensionInfo {
+        arch_versions,
+        arch_signatures,
+        metadata: metadata(extension),
+    })
+}
+
+const ENGINE_PROPERTY: &str = "Microsoft.VisualStudio.Code.Engine";
+const PRE_RELEASE_PROPERTY: &str = "Microsoft.VisualStudio.Code.PreRelease";
+
+// Every version in the response, one entry per version with all of its platforms
+pub fn parse_versions(response_json: &serde_json::Value) -> Result<Vec<VersionInfo>, &'static str> {
+    let versions_array = response_json["results"][0]["extensions"][0]["versions"]
+        .as_array()
+        .ok_or("Failed to get versions array")?;
+    let mut versions = Vec::new();
+    for v in versions_array {
+        if let Some(version_str) = v["version"].as_str() {
+            add_build(
+                &mut versions,
+                version_str,
+                v["targetPlatform"].as_str(),
+                string(&v["lastUpdated"]),
+                property(v, PRE_RELEASE_PROPERTY).as_deref() == Some("true"),
+                property(v, ENGINE_PROPERTY),
+            );
         }
     }
-    Ok(ExtensionInfo { arch_versions })
+    Ok(versions)
+}
+
+const SOURCE_LINK: &str = "Microsoft.VisualStudio.Services.Links.Source";
+const GITHUB_LINK: &str = "Microsoft.VisualStudio.Services.Links.GitHub";
+const LICENSE_ASSET_TYPE: &str = "Microsoft.VisualStudio.Services.Content.License";
+
+fn string(value: &serde_json::Value) -> Option<String> {
+    value
+        .as_str()
+        .filter(|s| !s.is_empty())
+        .map(|s| s.to_string())
+}
+
+fn strings(value: &serde_json::Value) -> Vec<String> {
+    value
+        .as_array()
+        .map(|items| items.iter().filter_map(string).collect())
+        .unwrap_or_default()
+}
+
+// Value of a "statistics" entry, e.g. "install" or "averagerating"
+fn statistic(extension: &serde_json::Value, name: &str) -> Option<f64> {
+    extension["statistics"]
+        .as_array()?
+        .iter()
+        .find(|statistic| statistic["statisticName"].as_str() == Some(name))
+        .and_then(|statistic| statistic["value"].as_f64())
+}
+
+// Value of a version property, e.g. the link to the source repository
+fn property(version: &serde_json::Value, key: &str) -> Option<String> {
+    version["properties"]
+        .as_array()?
+        .iter()
+        .find(|property| property["key"].as_str() == Some(key))
+        .and_then(|property| string(&property["value"]))
+}
+
+pub fn metadata(extension: &serde_json::Value) -> Metadata {
+    let publisher = &extension["publisher"];
+    let latest = &extension["versions"][0];
+    let verified = publisher["isDomainVerified"].as_bool().or_else(|| {
+        publisher["flags"]
+            .as_str()
+            .map(|flags| flags.split(',').any(|flag| flag.trim() == "verified"))
+    });
+    Metadata {
+        display_name: string(&extension["displayName"]),
+        description: string(&extension["shortDescription"]),
+        publisher_display_name: string(&publisher["displayName"]),
+        publisher_verified: verified,
+        categories: strings(&extension["categories"]),
+        tags: strings(&extension["tags"]),
+        install_count: statistic(extension, "install").map(|count| count as u64),
+        rating: statistic(extension, "averagerating"),
+        rating_count: statistic(extension, "ratingcount").map(|count| count as u64),
+        last_updated: string(&extension["lastUpdated"]),
+        repository: property(latest, SOURCE_LINK).or_else(|| property(latest, GITHUB_LINK)),
+        license: asset_source(latest, LICENSE_ASSET_TYPE),
+    }
+}
+
+// Find the url of an asset in the "files" of a version
+fn asset_source(version: &serde_json::Value, asset_type: &str) -> Option<String> {
+    version["files"]
+        .as_array()?
+        .iter()
+        .find(|file| file["assetType"].as_str() == Some(asset_type))
+        .and_then(|file| file["source"].as_str())
+        .map(|source| source.to_string())
 }
 
 #[cfg(test)]
@@ -34,7 +167,13 @@ mod tests {
                 {
                     "versions": [
                         {
-                            "version": "1.11.3"
+                            "version": "1.11.3",
+                            "files": [
+                                {
+                                    "assetType": "Microsoft.VisualStudio.Services.VsixSignature",
+                                    "source": "https://example.com/1.11.3/Microsoft.VisualStudio.Services.VsixSignature"
+                                }
+                            ]
                         },
                         {
                             "version": "1.11.0",
@@ -86,8 +225,154 @@ mod tests {
         }
     ]
 }
+"#;
+
+    const METADATA: &str = r#"
+{
+    "results": [
+        {
+            "extensions": [
+                {
+                    "displayName": "YAML",
+                    "shortDescription": "YAML Language Support",
+                    "publisher": {"displayName": "Red Hat", "flags": "verified", "isDomainVerified": true},
+                    "categories": ["Programming Languages", "Linters"],
+                    "tags": ["yaml", ""],
+                    "lastUpdated": "2024-05-01T12:00:00.000Z",
+                    "statistics": [
+                        {"statisticName": "install", "value": 21000000.0},
+                        {"statisticName": "averagerating", "value": 4.25},
+                        {"statisticName": "ratingcount", "value": 180.0}
+                    ],
+                    "versions": [
+                        {
+                            "version": "1.14.0",
+                            "files": [
+                                {
+                                    "assetType": "Microsoft.VisualStudio.Services.Content.License",
+                                    "source": "https://example.com/1.14.0/license"
+                                }
+                            ],
+                            "properties": [
+                                {
+                                    "key": "Microsoft.VisualStudio.Services.Links.Source",
+                                    "value": "https://github.com/redhat-developer/vscode-yaml.git"
+                                }
+                            ]
+                        }
+                    ]
+                }
+            ]
+        }
+    ]
+}
 "#;
 
+    #[test]
+    fn test_parse_metadata() {
+        let response_json: serde_json::Value = serde_json::from_str(METADATA).unwrap();
+        let metadata = parse(&response_json).unwrap().metadata;
+        assert_eq!(
+            metadata,
+            Metadata {
+                display_name: Some("YAML".to_string()),
+                description: Some("YAML Language Support".to_string()),
+                publisher_display_name: Some("Red Hat".to_string()),
+                publisher_verified: Some(true),
+                categories: vec!["Programming Languages".to_string(), "Linters".to_string()],
+                tags: vec!["yaml".to_string()],
+                install_count: Some(21000000),
+                rating: Some(4.25),
+                rating_count: Some(180),
+                last_updated: Some("2024-05-01T12:00:00.000Z".to_string()),
+                repository: Some("https://github.com/redhat-developer/vscode-yaml.git".to_string()),
+                license: Some("https://example.com/1.14.0/license".to_string()),
+            }
+        );
+
+        // Nothing but versions
+        let response_json: serde_json::Value = serde_json::from_str(LOG2).unwrap();
+        assert_eq!(parse(&response_json).unwrap().metadata, Metadata::default());
+    }
+
+    const VERSIONS: &str = r#"
+{
+    "results": [
+        {
+            "extensions": [
+                {
+                    "versions": [
+                        {
+                            "version": "1.1.0",
+                            "targetPlatform": "linux-x64",
+                            "lastUpdated": "2024-05-02T00:00:00Z",
+                            "properties": [
+                                {"key": "Microsoft.VisualStudio.Code.Engine", "value": "^1.80.0"},
+                                {"key": "Microsoft.VisualStudio.Code.PreRelease", "value": "true"}
+                            ]
+                        },
+                        {"version": "1.1.0", "targetPlatform": "win32-x64"},
+                        {"version": "1.0.0", "lastUpdated": "2024-01-01T00:00:00Z"}
+                    ]
+                }
+            ]
+        }
+    ]
+}
+"#;
+
+    #[test]
+    fn test_parse_batch() {
+        let response_json = serde_json::json!({
+            "results": [{
+                "extensions": [
+                    {
+                        "publisher": {"publisherName": "Redhat"},
+                        "extensionName": "Vscode-Yaml",
+                        "versions": [{"version": "1.14.0"}]
+                    },
+                    {
+                        "publisher": {"publisherName": "rust-lang"},
+                        "extensionName": "rust-analyzer",
+                        "versions": [{"version": "0.4.2304", "targetPlatform": "linux-x64"}]
+                    },
+                    {"extensionName": "no-publisher", "versions": [{"version": "1.0.0"}]}
+                ]
+            }]
+        });
+        let infos = parse_batch(&response_json);
+        assert_eq!(infos.len(), 2);
+        assert_eq!(
+            infos["redhat.vscode-yaml"].arch_versions.get(&None),
+            Some(&"1.14.0".to_string())
+        );
+        assert!(infos.contains_key("rust-lang.rust-analyzer"));
+    }
+
+    #[test]
+    fn test_parse_versions() {
+        let response_json: serde_json::Value = serde_json::from_str(VERSIONS).unwrap();
+        assert_eq!(
+            parse_versions(&response_json).unwrap(),
+            vec![
+                VersionInfo {
+                    version: "1.1.0".to_string(),
+                    platforms: vec!["linux-x64".to_string(), "win32-x64".to_string()],
+                    published: Some("2024-05-02T00:00:00Z".to_string()),
+                    pre_release: true,
+                    engine: Some("^1.80.0".to_string()),
+                },
+                VersionInfo {
+                    version: "1.0.0".to_string(),
+                    platforms: vec!["universal".to_string()],
+                    published: Some("2024-01-01T00:00:00Z".to_string()),
+                    pre_release: false,
+                    engine: None,
+                },
+            ]
+        );
+    }
+
     #[test]
     fn test_parse_parameterized_log1() {
         let response_json: serde_json::Value = serde_json::from_str(LOG1).unwrap();
@@ -125,7 +410,7 @@ mod tests {
     fn test_parse_parameterized_log3() {
         let response_json: serde_json::Value = serde_json::from_str(LOG3).unwrap();
         let extension_info = parse(&response_json).unwrap();
-        let arch_versions = extension_info.arch_versions;
+        let arch_versions = &extension_info.arch_versions;
         let expected = vec![
             (None, "1.11.3".to_string()),
             (Some("linux-x64".to_string()), "1.11.0".to_string()),
@@ -133,5 +418,15 @@ mod tests {
         for (platform, version) in expected {
             assert_eq!(arch_versions.get(&platform), Some(&version));
         }
+        assert_eq!(
+            extension_info
+                .arch_signatures
+                .get(&None)
+                .map(|s| s.as_str()),
+            Some("https://example.com/1.11.3/Microsoft.VisualStudio.Services.VsixSignature")
+        );
+        assert!(!extension_info
+            .arch_signatures
+            .contains_key(&Some("linux-x64".to_string())));
     }
 }
@@ -1,4 +1,5 @@
 use crate::extensions::info;
+use std::cmp::Ordering;
 
 use super::version;
 
@@ -80,18 +81,28 @@ pub fn decide_target(
             return Some(current);
         }
         // Check if the current version is supported and if there is a version for None
-        if let Some(none_version_str) = info.arch_versions.get(&None) {
-            let current_version =
-                version::parse(info.arch_versions.get(&Some(current.clone())).unwrap());
-
-            let none_version = version::parse(none_version_str);
-            if current_version > none_version {
-                Some(current)
-            } else {
-                None
+        match (
+            info.arch_versions.get(&Some(current.clone())),
+            info.arch_versions.get(&None),
+        ) {
+            (Some(current_version_str), Some(none_version_str)) => {
+                let newer = match (
+                    version::parse(current_version_str),
+                    version::parse(none_version_str),
+                ) {
+                    (Ok(current_version), Ok(none_version)) => current_version > none_version,
+                    // Not major.minor.patch, compare the numeric parts
+                    _ => {
+                        version::compare(current_version_str, none_version_str) == Ordering::Greater
+                    }
+                };
+                if newer {
+                    Some(current)
+                } else {
+                    None
+                }
             }
-        } else {
-            Some(current)
+            _ => Some(current),
         }
     } else {
         None
@@ -118,6 +129,7 @@ mod tests {
                 map.insert(Some("aarch64".to_string()), "10.1.0".to_string());
                 map
             },
+            ..Default::default()
         }
     }
 
@@ -132,7 +144,10 @@ mod tests {
     ) {
         let arch_versions = pattern1().arch_versions;
         let current = "linux-x64".to_string();
-        let info = ExtensionInfo { arch_versions };
+        let info = ExtensionInfo {
+            arch_versions,
+            ..Default::default()
+        };
         assert_eq!(
             expected,
             decide_target(target, current, info.clone()).as_deref()
@@ -145,7 +160,10 @@ mod tests {
         let mut arch_versions = HashMap::new();
         arch_versions.insert(Some("x64".to_string()), "ver".to_string());
         arch_versions.insert(Some("x86".to_string()), "ver".to_string());
-        let info = ExtensionInfo { arch_versions };
+        let info = ExtensionInfo {
+            arch_versions,
+            ..Default::default()
+        };
         assert_eq!(None, decide_target(None, current.clone(), info.clone()));
         assert_eq!(
             Some("x64".to_string()),
@@ -157,9 +175,7 @@ mod tests {
     #[test]
     fn test_when_no_supported_architectures() {
         let current = "linux-x64".to_string();
-        let info = ExtensionInfo {
-            arch_versions: HashMap::new(),
-        };
+        let info = ExtensionInfo::default();
         assert_eq!(None, decide_target(None, current.clone(), info.clone()));
         assert_eq!(None, decide_target(Some("x64"), current, info));
     }
@@ -0,0 +1,955 @@
+use crate::error;
+use crate::extensions::client::HttpClient;
+use crate::extensions::info;
+use crate::extensions::info::{ExtensionInfo, VersionInfo};
+use crate::extensions::local;
+use crate::extensions::openvsx;
+use crate::extensions::platform;
+use crate::extensions::resume;
+use crate::extensions::search;
+use crate::extensions::url;
+use async_trait::async_trait;
+use reqwest::{header, StatusCode};
+use std::cell::RefCell;
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+
+// Where extensions are resolved and downloaded from
+#[async_trait(?Send)]
+pub trait Registry {
+    // Short name to show which registry served an extension
+    fn name(&self) -> &str;
+    // Latest version of the extension per target platform
+    async fn query(
+        &self,
+        publisher: &str,
+        extension_name: &str,
+    ) -> Result<ExtensionInfo, error::Error>;
+    // Query many extensions (publisher.name) ahead of `query`, for registries
+    // that can batch them. Returns the lowercase IDs that were found.
+    async fn prefetch(&self, _ids: &[String]) -> HashSet<String> {
+        HashSet::new()
+    }
+    // Every version of the extension, newest first
+    async fn versions(
+        &self,
+        _publisher: &str,
+        _extension_name: &str,
+    ) -> Result<Vec<VersionInfo>, error::Error> {
+        Err(error::Error::Unsupported(format!(
+            "{} does not list versions",
+            self.name()
+        )))
+    }
+    fn download_url(
+        &self,
+        publisher: &str,
+        extension_name: &str,
+        version: &str,
+        target_platform: Option<String>,
+    ) -> String;
+    async fn fetch(&self, request: AssetRequest<'_>) -> Result<Asset, Box<dyn Error>>;
+    // Size of the package at the url, if the registry can tell without downloading it
+    async fn size(&self, _url: &str) -> Option<u64> {
+        None
+    }
+    // Extensions with an ID similar to one that no registry has, to suggest instead
+    async fn suggestions(&self, _id: &str) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+// The registry, version and platform an extension is served from
+pub struct Resolution<'a> {
+    pub registry: &'a dyn Registry,
+    pub info: ExtensionInfo,
+    pub target_platform: Option<String>,
+    pub version: String,
+}
+
+// Query the registries in order and return the first one that has the extension
+pub async fn query_first<'a>(
+    registries: &'a [Box<dyn Registry>],
+    publisher: &str,
+    extension_name: &str,
+) -> Result<(&'a dyn Registry, ExtensionInfo), error::Error> {
+    let id = format!("{publisher}.{extension_name}");
+    let mut reasons = Vec::new();
+    for registry in registries {
+        match registry.query(publisher, extension_name).await {
+            Ok(info) if !info.arch_versions.is_empty() => return Ok((registry.as_ref(), info)),
+            Ok(_) => reasons.push((
+                registry.name().to_string(),
+                error::Error::NotFound {
+                    id: id.clone(),
+                    location: registry.name().to_string(),
+                    suggestions: Vec::new(),
+                },
+            )),
+            Err(e) => reasons.push((registry.name().to_string(), e)),
+        }
+    }
+    Err(unresolved(registries, id, reasons).await)
+}
+
+// Every registry failed, with one reason each in the same order. Only now are the
+// registries that do not have the extension asked for similar ones, so no search
+// is spent on an extension that a later registry provides.
+async fn unresolved(
+    registries: &[Box<dyn Registry>],
+    id: String,
+    mut reasons: Vec<(String, error::Error)>,
+) -> error::Error {
+    for (registry, (_, reason)) in registries.iter().zip(reasons.iter_mut()) {
+        if let error::Error::NotFound { suggestions, .. } = reason {
+            if suggestions.is_empty() {
+                *suggestions = registry.suggestions(&id).await;
+            }
+        }
+    }
+    error::Error::Unresolved { id, reasons }
+}
+
+// Let the registries batch the queries of a list of extensions. An extension
+// found in one registry is not prefetched from the registries after it.
+// Invalid IDs are left to `query` to report.
+pub async fn prefetch(registries: &[Box<dyn Registry>], ids: &[String]) {
+    let mut pending: Vec<String> = ids
+        .iter()
+        .filter(|id| info::validate_extension_name(id))
+        .map(|id| id.to_lowercase())
+        .collect();
+    pending.sort();
+    pending.dedup();
+    for registry in registries {
+        if pending.is_empty() {
+            break;
+        }
+        let found = registry.prefetch(&pending).await;
+        pending.retain(|id| !found.contains(id));
+    }
+}
+
+// Resolve the extension against the first registry that has a version
+// for the requested (or current) platform
+pub async fn resolve<'a>(
+    registries: &'a [Box<dyn Registry>],
+    publisher: &str,
+    extension_name: &str,
+    os_arch: Option<&str>,
+    verbose: bool,
+) -> Result<Resolution<'a>, error::Error> {
+    let id = format!("{publisher}.{extension_name}");
+    let current = platform::get_current();
+    let mut reasons = Vec::new();
+    for registry in registries {
+        let info = match registry.query(publisher, extension_name).await {
+            Ok(info) => info,
+            Err(e) => {
+                reasons.push((registry.name().to_string(), e));
+                continue;
+            }
+        };
+        if verbose {
+            println!(
+                "Latest version of {id} in {}: {:?}",
+                registry.name(),
+                info.arch_versions
+            );
+        }
+        let target_platform = platform::decide_target(os_arch, current.clone(), info.clone());
+        match info.arch_versions.get(&target_platform).cloned() {
+            Some(version) => {
+                return Ok(Resolution {
+                    registry: registry.as_ref(),
+                    info,
+                    target_platform,
+                    version,
+                })
+            }
+            None => reasons.push((
+                registry.name().to_string(),
+                error::Error::PlatformUnavailable {
+                    id: id.clone(),
+                    platform: os_arch.unwrap_or(&current).to_string(),
+                },
+            )),
+        }
+    }
+    Err(unresolved(registries, id, reasons).await)
+}
+
+pub struct AssetRequest<'a> {
+    pub url: &'a str,
+    // Request only the bytes after the offset, if the validator still matches
+    pub resume_from: Option<(u64, &'a str)>,
+    pub accept_encoding: &'a str,
+}
+
+pub struct Asset {
+    pub status: StatusCode,
+    pub content_encoding: Option<String>,
+    pub content_range: Option<String>,
+    pub content_length: Option<u64>,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub body: Box<dyn AssetBody>,
+}
+
+impl Asset {
+    pub async fn bytes(mut self) -> Result<Vec<u8>, Box<dyn Error>> {
+        let mut content = Vec::new();
+        while let Some(chunk) = self.body.chunk().await? {
+            content.extend_from_slice(&chunk);
+        }
+        Ok(content)
+    }
+}
+
+#[async_trait(?Send)]
+pub trait AssetBody {
+    // Next part of the body, None at the end
+    async fn chunk(&mut self) -> Result<Option<Vec<u8>>, Box<dyn Error>>;
+}
+
+#[async_trait(?Send)]
+impl AssetBody for reqwest::Response {
+    async fn chunk(&mut self) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        Ok(reqwest::Response::chunk(self)
+            .await?
+            .map(|chunk| chunk.to_vec()))
+    }
+}
+
+pub async fn fetch_http(
+    client: &HttpClient,
+    request: AssetRequest<'_>,
+) -> Result<Asset, Box<dyn Error>> {
+    let mut builder = client
+        .get(request.url)
+        .header(header::ACCEPT_ENCODING, request.accept_encoding);
+    if let Some((offset, validator)) = request.resume_from {
+        builder = builder
+            .header(header::RANGE, resume::range_header(offset))
+            .header(header::IF_RANGE, validator);
+    }
+    let response = builder.send().await?;
+    let header_value = |name: header::HeaderName| {
+        response
+            .headers()
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|s| s.to_string())
+    };
+    Ok(Asset {
+        status: response.status(),
+        content_encoding: header_value(header::CONTENT_ENCODING),
+        content_range: header_value(header::CONTENT_RANGE),
+        content_length: response.content_length(),
+        etag: header_value(header::ETAG),
+        last_modified: header_value(header::LAST_MODIFIED),
+        body: Box::new(response),
+    })
+}
+
+// Content-Length of a HEAD request, None if the server does not send it
+pub async fn head_size(client: &HttpClient, url: &str) -> Option<u64> {
+    let response = client
+        .head(url)
+        .header(header::ACCEPT_ENCODING, "identity")
+        .send()
+        .await
+        .ok()?;
+    if !response.status().is_success() {
+        return None;
+    }
+    response
+        .headers()
+        .get(header::CONTENT_LENGTH)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()
+}
+
+// Visual Studio Marketplace, or a gallery-compatible mirror of it
+pub struct Marketplace {
+    gallery: url::Gallery,
+    client: HttpClient,
+    verbose: bool,
+    // Prefetched extensions by lowercase publisher.name, None if the gallery does not know it
+    cache: RefCell<HashMap<String, Option<ExtensionInfo>>>,
+}
+
+impl Marketplace {
+    pub fn new(gallery: url::Gallery, client: HttpClient, verbose: bool) -> Marketplace {
+        Marketplace {
+            gallery,
+            client,
+            verbose,
+            cache: RefCell::new(HashMap::new()),
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl Registry for Marketplace {
+    fn name(&self) -> &str {
+        "msft"
+    }
+
+    async fn query(
+        &self,
+        publisher: &str,
+        extension_name: &str,
+    ) -> Result<ExtensionInfo, error::Error> {
+        let id = format!("{publisher}.{extension_name}");
+        let cached = self.cache.borrow().get(&id.to_lowercase()).cloned();
+        match cached {
+            Some(Some(info)) => return Ok(info),
+            Some(None) => return Err(info::not_found(&id)),
+            None => {}
+        }
+        info::get(
+            &self.client,
+            &self.gallery.query_url(),
+            publisher,
+            extension_name,
+            self.verbose,
+        )
+        .await
+    }
+
+    async fn prefetch(&self, ids: &[String]) -> HashSet<String> {
+        let ids: Vec<String> = ids
+            .iter()
+            .filter(|id| !self.cache.borrow().contains_key(*id))
+            .cloned()
+            .collect();
+        let infos =
+            match info::get_many(&self.client, &self.gallery.query_url(), &ids, self.verbose).await
+            {
+                Ok(infos) => infos,
+                Err(e) => {
+                    // Each extension is queried on its own later
+                    if self.verbose {
+                        println!("Batch query failed, querying one by one: {e}");
+                    }
+                    return HashSet::new();
+                }
+            };
+        // Missing extensions are cached too, query reports them as not found
+        let mut cache = self.cache.borrow_mut();
+        for id in ids {
+            let info = infos.get(&id).cloned();
+            cache.insert(id, info);
+        }
+        cache
+            .iter()
+            .filter(|(_, info)| info.is_some())
+            .map(|(id, _)| id.clone())
+            .collect()
+    }
+
+    async fn versions(
+        &self,
+        publisher: &str,
+        extension_name: &str,
+    ) -> Result<Vec<VersionInfo>, error::Error> {
+        info::versions(
+            &self.client,
+            &self.gallery.query_url(),
+            publisher,
+            extension_name,
+            self.verbose,
+        )
+        .await
+    }
+
+    fn download_url(
+        &self,
+        publisher: &str,
+        extension_name: &str,
+        version: &str,
+        target_platform: Option<String>,
+    ) -> String {
+        self.gallery
+            .for_download(publisher, extension_name, version, target_platform)
+    }
+
+    async fn fetch(&self, request: AssetRequest<'_>) -> Result<Asset, Box<dyn Error>> {
+        fetch_http(&self.client, request).await
+    }
+
+    async fn size(&self, url: &str) -> Option<u64> {
+        head_size(&self.client, url).await
+    }
+
+    // A failing search only leaves out the suggestions
+    async fn suggestions(&self, id: &str) -> Vec<String> {
+        search::similar(&self.client, &self.gallery.query_url(), id, self.verbose).await
+    }
+}
+
+// Open VSX registry (open-vsx.org or a self-hosted instance)
+pub struct OpenVsx {
+    base_url: String,
+    client: HttpClient,
+    verbose: bool,
+}
+
+impl OpenVsx {
+    pub fn new(base_url: &str, client: HttpClient, verbose: bool) -> OpenVsx {
+        OpenVsx {
+            base_url: base_url.to_string(),
+            client,
+            verbose,
+        }
+    }
+}
+
+#[async_trait(?Send)]
+impl Registry for OpenVsx {
+    fn name(&self) -> &str {
+        "openvsx"
+    }
+
+    async fn query(
+        &self,
+        publisher: &str,
+        extension_name: &str,
+    ) -> Result<ExtensionInfo, error::Error> {
+        openvsx::get(
+            &self.client,
+            &self.base_url,
+            publisher,
+            extension_name,
+            self.verbose,
+        )
+        .await
+    }
+
+    async fn versions(
+        &self,
+        publisher: &str,
+        extension_name: &str,
+    ) -> Result<Vec<VersionInfo>, error::Error> {
+        openvsx::versions(
+            &self.client,
+            &self.base_url,
+            publisher,
+            extension_name,
+            self.verbose,
+        )
+        .await
+    }
+
+    fn download_url(
+        &self,
+        publisher: &str,
+        extension_name: &str,
+        version: &str,
+        target_platform: Option<String>,
+    ) -> String {
+        openvsx::for_download(
+            &self.base_url,
+            publisher,
+            extension_name,
+            version,
+            target_platform,
+        )
+    }
+
+    async fn fetch(&self, request: AssetRequest<'_>) -> Result<Asset, Box<dyn Error>> {
+        fetch_http(&self.client, request).await
+    }
+
+    async fn size(&self, url: &str) -> Option<u64> {
+        head_size(&self.client, url).await
+    }
+}
+
+// Directory of previously harvested packages, e.g. on a USB drive
+pub struct LocalDirectory {
+    root: std::path::PathBuf,
+    packages: Vec<local::LocalPackage>,
+}
+
+impl LocalDirectory {
+    // The directory is scanned once, so the manifests are not read per extension
+    pub fn new(location: &str, verbose: bool) -> Result<LocalDirectory, Box<dyn Error>> {
+        let root = local::root_path(location)?;
+        let packages = local::scan(&root, verbose)?;
+        Ok(LocalDirectory { root, packages })
+    }
+}
+
+// Whole file content as a single chunk
+struct FileBody(Option<Vec<u8>>);
+
+#[async_trait(?Send)]
+impl AssetBody for FileBody {
+    async fn chunk(&mut self) -> Result<Option<Vec<u8>>, Box<dyn Error>> {
+        Ok(self.0.take())
+    }
+}
+
+#[async_trait(?Send)]
+impl Registry for LocalDirectory {
+    fn name(&self) -> &str {
+        "local"
+    }
+
+    async fn query(
+        &self,
+        publisher: &str,
+        extension_name: &str,
+    ) -> Result<ExtensionInfo, error::Error> {
+        let extension_info = local::info(&self.packages, publisher, extension_name);
+        if extension_info.arch_versions.is_empty() {
+            return Err(error::Error::NotFound {
+                id: format!("{publisher}.{extension_name}"),
+                location: self.root.display().to_string(),
+                suggestions: Vec::new(),
+            });
+        }
+        Ok(extension_info)
+    }
+
+    async fn versions(
+        &self,
+        publisher: &str,
+        extension_name: &str,
+    ) -> Result<Vec<VersionInfo>, error::Error> {
+        Ok(local::versions(&self.packages, publisher, extension_name))
+    }
+
+    fn download_url(
+        &self,
+        publisher: &str,
+        extension_name: &str,
+        version: &str,
+        target_platform: Option<String>,
+    ) -> String {
+        match local::find(
+            &self.packages,
+            publisher,
+            extension_name,
+            version,
+            target_platform.as_deref(),
+        ) {
+            Some(package) => local::file_url(&package.path),
+            None => local::file_url(
+                &self
+                    .root
+                    .join(format!("{publisher}.{extension_name}-{version}.vsix")),
+            ),
+        }
+    }
+
+    // Ranges are not supported, the whole file is returned every time
+    async fn fetch(&self, request: AssetRequest<'_>) -> Result<Asset, Box<dyn Error>> {
+        let path = local::url_to_path(request.url)?;
+        let content =
+            std::fs::read(&path).map_err(|e| format!("Failed to read {}: {e}", path.display()))?;
+        Ok(Asset {
+            status: StatusCode::OK,
+            content_encoding: None,
+            content_range: None,
+            content_length: Some(content.len() as u64),
+            etag: None,
+            last_modified: None,
+            body: Box::new(FileBody(Some(content))),
+        })
+    }
+
+    async fn size(&self, url: &str) -> Option<u64> {
+        let path = local::url_to_path(url).ok()?;
+        std::fs::metadata(path).ok().map(|metadata| metadata.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    // Registry with a fixed set of versions
+    struct StaticRegistry {
+        name: &'static str,
+        arch_versions: Option<HashMap<Option<String>, String>>,
+    }
+
+    impl StaticRegistry {
+        fn boxed(
+            name: &'static str,
+            versions: Option<&[(Option<&str>, &str)]>,
+        ) -> Box<dyn Registry> {
+            Box::new(StaticRegistry {
+                name,
+                arch_versions: versions.map(|versions| {
+                    versions
+                        .iter()
+                        .map(|(platform, version)| {
+                            (platform.map(|p| p.to_string()), version.to_string())
+                        })
+                        .collect()
+                }),
+            })
+        }
+    }
+
+    #[async_trait(?Send)]
+    impl Registry for StaticRegistry {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        async fn query(
+            &self,
+            publisher: &str,
+            extension_name: &str,
+        ) -> Result<ExtensionInfo, error::Error> {
+            match &self.arch_versions {
+                Some(arch_versions) => Ok(ExtensionInfo {
+                    arch_versions: arch_versions.clone(),
+                    ..Default::default()
+                }),
+                None => Err(error::Error::NotFound {
+                    id: format!("{publisher}.{extension_name}"),
+                    location: self.name.to_string(),
+                    suggestions: Vec::new(),
+                }),
+            }
+        }
+
+        fn download_url(
+            &self,
+            _publisher: &str,
+            _extension_name: &str,
+            _version: &str,
+            _target_platform: Option<String>,
+        ) -> String {
+            String::new()
+        }
+
+        async fn fetch(&self, _request: AssetRequest<'_>) -> Result<Asset, Box<dyn Error>> {
+            Err(Box::from("not supported"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_resolve_first_registry_with_platform() {
+        let registries = vec![
+            StaticRegistry::boxed("missing", None),
+            StaticRegistry::boxed("other-platform", Some(&[(Some("win32-x64"), "2.0.0")])),
+            StaticRegistry::boxed("match", Some(&[(Some("linux-x64"), "1.0.0")])),
+            StaticRegistry::boxed("later", Some(&[(Some("linux-x64"), "3.0.0")])),
+        ];
+        let resolution = resolve(&registries, "publisher", "name", Some("linux-x64"), false)
+            .await
+            .unwrap();
+        assert_eq!(resolution.registry.name(), "match");
+        assert_eq!(resolution.version, "1.0.0");
+        assert_eq!(resolution.target_platform.as_deref(), Some("linux-x64"));
+    }
+
+    #[tokio::test]
+    async fn test_resolve_universal() {
+        let registries = vec![StaticRegistry::boxed("universal", Some(&[(None, "1.0.0")]))];
+        let resolution = resolve(&registries, "publisher", "name", None, false)
+            .await
+            .unwrap();
+        assert_eq!(resolution.target_platform, None);
+        assert_eq!(resolution.version, "1.0.0");
+    }
+
+    #[tokio::test]
+    async fn test_resolve_nothing_found() {
+        let registries = vec![
+            StaticRegistry::boxed("missing", None),
+            StaticRegistry::boxed("other-platform", Some(&[(Some("win32-x64"), "2.0.0")])),
+        ];
+        let error = resolve(&registries, "publisher", "name", Some("linux-x64"), false)
+            .await
+            .err()
+            .unwrap();
+        assert!(error
+            .to_string()
+            .contains("missing: publisher.name does not exist in missing"));
+        assert!(error
+            .to_string()
+            .contains("other-platform: publisher.name has no version for linux-x64"));
+        match error {
+            error::Error::Unresolved { id, reasons } => {
+                assert_eq!(id, "publisher.name");
+                assert!(matches!(reasons[0].1, error::Error::NotFound { .. }));
+                assert!(matches!(
+                    &reasons[1].1,
+                    error::Error::PlatformUnavailable { platform, .. } if platform == "linux-x64"
+                ));
+            }
+            error => panic!("unexpected error {error}"),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_query_first() {
+        let registries = vec![
+            StaticRegistry::boxed("missing", None),
+            StaticRegistry::boxed("found", Some(&[(None, "1.0.0")])),
+        ];
+        let (registry, info) = query_first(&registries, "publisher", "name").await.unwrap();
+        assert_eq!(registry.name(), "found");
+        assert_eq!(info.arch_versions.len(), 1);
+    }
+
+    // Registry without any extension that records the IDs it was asked to suggest for
+    struct SuggestingRegistry {
+        asked: std::rc::Rc<RefCell<Vec<String>>>,
+    }
+
+    #[async_trait(?Send)]
+    impl Registry for SuggestingRegistry {
+        fn name(&self) -> &str {
+            "suggesting"
+        }
+
+        async fn query(
+            &self,
+            publisher: &str,
+            extension_name: &str,
+        ) -> Result<ExtensionInfo, error::Error> {
+            Err(error::Error::NotFound {
+                id: format!("{publisher}.{extension_name}"),
+                location: self.name().to_string(),
+                suggestions: Vec::new(),
+            })
+        }
+
+        fn download_url(
+            &self,
+            _publisher: &str,
+            _extension_name: &str,
+            _version: &str,
+            _target_platform: Option<String>,
+        ) -> String {
+            String::new()
+        }
+
+        async fn fetch(&self, _request: AssetRequest<'_>) -> Result<Asset, Box<dyn Error>> {
+            Err(Box::from("not supported"))
+        }
+
+        async fn suggestions(&self, id: &str) -> Vec<String> {
+            self.asked.borrow_mut().push(id.to_string());
+            vec!["publisher.similar".to_string()]
+        }
+    }
+
+    #[tokio::test]
+    async fn test_suggestions_after_all_registries_failed() {
+        let asked = std::rc::Rc::new(RefCell::new(Vec::new()));
+        let suggesting = || -> Box<dyn Registry> {
+            Box::new(SuggestingRegistry {
+                asked: asked.clone(),
+            })
+        };
+
+        // A later registry has the extension, nothing is searched
+        let registries = vec![
+            suggesting(),
+            StaticRegistry::boxed("found", Some(&[(None, "1.0.0")])),
+        ];
+        resolve(&registries, "publisher", "name", None, false)
+            .await
+            .unwrap();
+        query_first(&registries, "publisher", "name").await.unwrap();
+        assert!(asked.borrow().is_empty());
+
+        let registries = vec![suggesting(), StaticRegistry::boxed("missing", None)];
+        let errors = [
+            resolve(&registries, "publisher", "name", None, false)
+                .await
+                .err()
+                .unwrap(),
+            query_first(&registries, "publisher", "name")
+                .await
+                .err()
+                .unwrap(),
+        ];
+        assert_eq!(*asked.borrow(), vec!["publisher.name", "publisher.name"]);
+        for error in errors {
+            assert!(error
+                .to_string()
+                .contains("did you mean publisher.similar?"));
+            match error {
+                error::Error::Unresolved { reasons, .. } => {
+                    assert!(matches!(
+                        &reasons[0].1,
+                        error::Error::NotFound { suggestions, .. } if suggestions == &["publisher.similar"]
+                    ));
+                    assert!(matches!(
+                        &reasons[1].1,
+                        error::Error::NotFound { suggestions, .. } if suggestions.is_empty()
+                    ));
+                }
+                error => panic!("unexpected error {error}"),
+            }
+        }
+    }
+
+    // Registry that finds a fixed set of IDs in prefetch and records what it was asked
+    struct BatchRegistry {
+        found: &'static [&'static str],
+        prefetched: std::rc::Rc<RefCell<Vec<Vec<String>>>>,
+    }
+
+    #[async_trait(?Send)]
+    impl Registry for BatchRegistry {
+        fn name(&self) -> &str {
+            "batch"
+        }
+
+        async fn query(
+            &self,
+            _publisher: &str,
+            _extension_name: &str,
+        ) -> Result<ExtensionInfo, error::Error> {
+            Err(error::Error::Unsupported("query".to_string()))
+        }
+
+        async fn prefetch(&self, ids: &[String]) -> HashSet<String> {
+            self.prefetched.borrow_mut().push(ids.to_vec());
+            ids.iter()
+                .filter(|id| self.found.contains(&id.as_str()))
+                .cloned()
+                .collect()
+        }
+
+        fn download_url(
+            &self,
+            _publisher: &str,
+            _extension_name: &str,
+            _version: &str,
+            _target_platform: Option<String>,
+        ) -> String {
+            String::new()
+        }
+
+        async fn fetch(&self, _request: AssetRequest<'_>) -> Result<Asset, Box<dyn Error>> {
+            Err(Box::from("not supported"))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_prefetch() {
+        let prefetched = std::rc::Rc::new(RefCell::new(Vec::new()));
+        let registries: Vec<Box<dyn Registry>> = vec![
+            Box::new(BatchRegistry {
+                found: &["a.one"],
+                prefetched: prefetched.clone(),
+            }),
+            Box::new(BatchRegistry {
+                found: &["b.two"],
+                prefetched: prefetched.clone(),
+            }),
+            Box::new(BatchRegistry {
+                found: &[],
+                prefetched: prefetched.clone(),
+            }),
+        ];
+        let ids: Vec<String> = ["A.One", "b.two", "a.one", "invalid", "c.three"]
+            .iter()
+            .map(|id| id.to_string())
+            .collect();
+        prefetch(&registries, &ids).await;
+        assert_eq!(
+            *prefetched.borrow(),
+            vec![
+                vec!["a.one", "b.two", "c.three"],
+                vec!["b.two", "c.three"],
+                vec!["c.three"],
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_marketplace_prefetched() {
+        let gallery = url::Gallery::new("http://127.0.0.1:9/_apis/public/gallery", None).unwrap();
+        let marketplace = Marketplace::new(gallery, HttpClient::default(), false);
+        let info = ExtensionInfo {
+            arch_versions: HashMap::from([(None, "1.0.0".to_string())]),
+            ..Default::default()
+        };
+        marketplace.cache.borrow_mut().extend([
+            ("publisher.name".to_string(), Some(info.clone())),
+            ("publisher.missing".to_string(), None),
+        ]);
+        assert_eq!(marketplace.query("Publisher", "Name").await.unwrap(), info);
+        let error = marketplace
+            .query("publisher", "missing")
+            .await
+            .err()
+            .unwrap();
+        assert!(matches!(
+            error,
+            error::Error::NotFound { id, suggestions, .. }
+                if id == "publisher.missing" && suggestions.is_empty()
+        ));
+        // Everything is cached, nothing is sent to the gallery
+        let found = marketplace
+            .prefetch(&[
+                "publisher.name".to_string(),
+                "publisher.missing".to_string(),
+            ])
+            .await;
+        assert_eq!(found, HashSet::from(["publisher.name".to_string()]));
+    }
+
+    #[tokio::test]
+    async fn test_local_directory() {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        let content = crate::extensions::vsix::tests::vsix("publisher", "name", "1.0.0", None);
+        std::fs::write(dir.join("publisher.name-1.0.0.vsix"), &content).unwrap();
+
+        let registry = LocalDirectory::new(dir.to_str().unwrap(), false).unwrap();
+        assert_eq!(registry.name(), "local");
+        let info = registry.query("publisher", "name").await.unwrap();
+        assert_eq!(
+            info.arch_versions.get(&None).map(|s| s.as_str()),
+            Some("1.0.0")
+        );
+        assert!(registry.query("publisher", "other").await.is_err());
+
+        let download_url = registry.download_url("publisher", "name", "1.0.0", None);
+        assert!(download_url.starts_with("file://"));
+        let asset = registry
+            .fetch(AssetRequest {
+                url: &download_url,
+                resume_from: None,
+                accept_encoding: "identity",
+            })
+            .await
+            .unwrap();
+        assert_eq!(asset.bytes().await.unwrap(), content);
+    }
+
+    #[test]
+    fn test_download_url() {
+        let client = HttpClient::default();
+        let marketplace = Marketplace::new(url::Gallery::default(), client.clone(), false);
+        assert_eq!(marketplace.name(), "msft");
+        assert_eq!(
+            marketplace.download_url("publisher", "name", "1.0.0", None),
+            url::Gallery::default().for_download("publisher", "name", "1.0.0", None)
+        );
+        let open_vsx = OpenVsx::new(openvsx::DEFAULT_URL, client, false);
+        assert_eq!(open_vsx.name(), "openvsx");
+        assert_eq!(
+            open_vsx.download_url("publisher", "name", "1.0.0", None),
+            "https://open-vsx.org/api/publisher/name/1.0.0/file/publisher.name-1.0.0.vsix"
+        );
+    }
+}
@@ -0,0 +1,169 @@
+use serde::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+// Validators of an interrupted download, stored next to the `.partial` file
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Default)]
+pub struct PartialMeta {
+    pub url: String,
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+    pub content_encoding: Option<String>,
+}
+
+impl PartialMeta {
+    // Value for the If-Range header.
+    // Weak ETags must not be used with If-Range, so fall back to Last-Modified.
+    pub fn validator(&self) -> Option<&str> {
+        match &self.etag {
+            Some(etag) if !etag.starts_with("W/") => Some(etag),
+            _ => self.last_modified.as_deref(),
+        }
+    }
+}
+
+pub struct Partial {
+    pub data_path: String,
+    pub meta_path: String,
+}
+
+impl Partial {
+    pub fn new(file_path: &str) -> Partial {
+        Partial {
+            data_path: format!("{file_path}.partial"),
+            meta_path: format!("{file_path}.partial.json"),
+        }
+    }
+
+    // Returns the stored validators and the number of bytes already received,
+    // if a resumable download of the same url exists
+    pub fn load(&self, url: &str) -> Option<(PartialMeta, u64)> {
+        let meta_content = fs::read_to_string(&self.meta_path).ok()?;
+        let meta: PartialMeta = serde_json::from_str(&meta_content).ok()?;
+        if meta.url != url || meta.validator().is_none() {
+            return None;
+        }
+        let len = fs::metadata(&self.data_path).ok()?.len();
+        if len == 0 {
+            return None;
+        }
+        Some((meta, len))
+    }
+
+    pub fn save_meta(&self, meta: &PartialMeta) -> Result<(), Box<dyn Error>> {
+        fs::write(&self.meta_path, serde_json::to_string(meta)?)?;
+        Ok(())
+    }
+
+    // Move the final content into place through the partial file, so the
+    // destination never holds a partly written package
+    pub fn finish(&self, content: &[u8], file_path: &str) -> std::io::Result<()> {
+        fs::write(&self.data_path, content)?;
+        fs::rename(&self.data_path, file_path)?;
+        self.clear();
+        Ok(())
+    }
+
+    pub fn clear(&self) {
+        for path in [&self.data_path, &self.meta_path] {
+            if Path::new(path).exists() {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+}
+
+pub fn range_header(offset: u64) -> String {
+    format!("bytes={offset}-")
+}
+
+// Parse the first byte position from "bytes 100-199/200"
+pub fn content_range_start(content_range: &str) -> Option<u64> {
+    let range = content_range.trim().strip_prefix("bytes ")?;
+    let (start, _) = range.split_once('-')?;
+    start.trim().parse().ok()
+}
+
+// Parse the complete length from "bytes 100-199/200" ("*" if unknown)
+pub fn content_range_total(content_range: &str) -> Option<u64> {
+    let (_, total) = content_range.rsplit_once('/')?;
+    total.trim().parse().ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn meta(etag: Option<&str>, last_modified: Option<&str>) -> PartialMeta {
+        PartialMeta {
+            url: "https://example.com/vspackage".to_string(),
+            etag: etag.map(|s| s.to_string()),
+            last_modified: last_modified.map(|s| s.to_string()),
+            content_encoding: Some("gzip".to_string()),
+        }
+    }
+
+    #[rstest]
+    #[case(Some("\"abc\""), None, Some("\"abc\""))]
+    #[case(
+        Some("\"abc\""),
+        Some("Wed, 21 Oct 2015 07:28:00 GMT"),
+        Some("\"abc\"")
+    )]
+    #[case(
+        Some("W/\"abc\""),
+        Some("Wed, 21 Oct 2015 07:28:00 GMT"),
+        Some("Wed, 21 Oct 2015 07:28:00 GMT")
+    )]
+    #[case(Some("W/\"abc\""), None, None)]
+    #[case(None, None, None)]
+    fn test_validator(
+        #[case] etag: Option<&str>,
+        #[case] last_modified: Option<&str>,
+        #[case] expected: Option<&str>,
+    ) {
+        assert_eq!(meta(etag, last_modified).validator(), expected);
+    }
+
+    #[rstest]
+    #[case("bytes 100-199/200", Some(100), Some(200))]
+    #[case("bytes 0-99/*", Some(0), None)]
+    #[case("bytes */200", None, Some(200))]
+    #[case("invalid", None, None)]
+    fn test_content_range(
+        #[case] content_range: &str,
+        #[case] start: Option<u64>,
+        #[case] total: Option<u64>,
+    ) {
+        assert_eq!(content_range_start(content_range), start);
+        assert_eq!(content_range_total(content_range), total);
+    }
+
+    #[test]
+    fn test_range_header() {
+        assert_eq!(range_header(1024), "bytes=1024-");
+    }
+
+    #[test]
+    fn test_partial_roundtrip() {
+        let dir = tempfile::tempdir().unwrap();
+        let file_path = dir.path().join("publisher.name-1.0.0.vsix");
+        let partial = Partial::new(file_path.to_str().unwrap());
+        let stored = meta(Some("\"abc\""), None);
+
+        // No partial file yet
+        assert_eq!(partial.load(&stored.url), None);
+
+        partial.save_meta(&stored).unwrap();
+        fs::write(&partial.data_path, b"0123456789").unwrap();
+        assert_eq!(partial.load(&stored.url), Some((stored.clone(), 10)));
+        // A different url must not resume the stored bytes
+        assert_eq!(partial.load("https://example.com/other"), None);
+
+        partial.clear();
+        assert!(!Path::new(&partial.data_path).exists());
+        assert!(!Path::new(&partial.meta_path).exists());
+    }
+}
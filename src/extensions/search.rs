@@ -0,0 +1,283 @@
+use crate::extensions::client::HttpClient;
+use crate::extensions::info::{post_query, Metadata};
+use crate::extensions::parse;
+use serde::Serialize;
+use serde_json::json;
+
+// Criteria of the gallery query
+const FILTER_TAG: u32 = 1;
+const FILTER_CATEGORY: u32 = 5;
+const FILTER_TARGET: u32 = 8;
+const FILTER_SEARCH_TEXT: u32 = 10;
+const FILTER_EXCLUDE_WITH_FLAGS: u32 = 12;
+// Unpublished extensions
+const EXCLUDE_FLAGS: &str = "4096";
+const TARGET: &str = "Microsoft.VisualStudio.Code";
+
+// sortBy of the gallery query
+pub const SORT_RELEVANCE: u32 = 0;
+pub const SORT_UPDATED: u32 = 1;
+pub const SORT_INSTALLS: u32 = 4;
+pub const SORT_RATING: u32 = 12;
+
+pub struct Query<'a> {
+    pub text: &'a str,
+    // 1-based
+    pub page: u32,
+    pub page_size: u32,
+    pub sort_by: u32,
+    pub category: Option<&'a str>,
+    pub tags: &'a [String],
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct SearchResult {
+    pub id: String,
+    // Latest version
+    pub version: String,
+    #[serde(flatten)]
+    pub metadata: Metadata,
+}
+
+#[derive(Serialize, Debug, Clone, PartialEq, Default)]
+pub struct SearchResults {
+    // Number of matches on all pages
+    pub total: u64,
+    pub results: Vec<SearchResult>,
+}
+
+fn payload(query: &Query) -> serde_json::Value {
+    let mut criteria = vec![
+        json!({"filterType": FILTER_TARGET, "value": TARGET}),
+        json!({"filterType": FILTER_EXCLUDE_WITH_FLAGS, "value": EXCLUDE_FLAGS}),
+        json!({"filterType": FILTER_SEARCH_TEXT, "value": query.text}),
+    ];
+    if let Some(category) = query.category {
+        criteria.push(json!({"filterType": FILTER_CATEGORY, "value": category}));
+    }
+    for tag in query.tags {
+        criteria.push(json!({"filterType": FILTER_TAG, "value": tag}));
+    }
+    json!({
+        "filters": [{
+            "criteria": criteria,
+            "pageNumber": query.page,
+            "pageSize": query.page_size,
+            "sortBy": query.sort_by,
+            "sortOrder": 0
+        }],
+        // Version properties, asset uri, statistics, categories and tags, latest version only
+        "flags": 916
+    })
+}
+
+pub fn parse_results(response_json: &serde_json::Value) -> Result<SearchResults, &'static str> {
+    let result = &response_json["results"][0];
+    let extensions = result["extensions"]
+        .as_array()
+        .ok_or("Failed to get extensions array")?;
+    let mut results = Vec::new();
+    for extension in extensions {
+        let (Some(publisher), Some(name)) = (
+            extension["publisher"]["publisherName"].as_str(),
+            extension["extensionName"].as_str(),
+        ) else {
+            continue;
+        };
+        results.push(SearchResult {
+            id: format!("{publisher}.{name}"),
+            version: extension["versions"][0]["version"]
+                .as_str()
+                .unwrap_or_default()
+                .to_string(),
+            metadata: parse::metadata(extension),
+        });
+    }
+    let total = result["resultMetadata"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter(|metadata| metadata["metadataType"].as_str() == Some("ResultCount"))
+        .filter_map(|metadata| metadata["metadataItems"].as_array())
+        .flatten()
+        .find(|item| item["name"].as_str() == Some("TotalCount"))
+        .and_then(|item| item["count"].as_u64())
+        .unwrap_or(results.len() as u64);
+    Ok(SearchResults { total, results })
+}
+
+// Full-text search on the gallery
+pub async fn search(
+    client: &HttpClient,
+    query_url: &str,
+    query: &Query<'_>,
+    verbose: bool,
+) -> Result<SearchResults, Box<dyn std::error::Error>> {
+    if verbose {
+        println!("Sending search for Marketplace API: {}", query.text);
+    }
+    let response_json = post_query(client, query_url, &payload(query)).await?;
+    Ok(parse_results(&response_json)?)
+}
+
+// Results searched for suggestions, and suggestions shown at most
+const SIMILAR_PAGE_SIZE: u32 = 20;
+const SIMILAR_MAX: usize = 3;
+
+// Edit distance of two IDs, ignoring case
+fn distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.to_lowercase().chars().collect();
+    let b: Vec<char> = b.to_lowercase().chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.iter().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+// The candidates close enough to the ID to be a typo of it, closest first
+pub fn closest(id: &str, candidates: &[String]) -> Vec<String> {
+    let max_distance = (id.len() / 5).max(2);
+    let mut close: Vec<(usize, &String)> = candidates
+        .iter()
+        .map(|candidate| (distance(id, candidate), candidate))
+        .filter(|(distance, _)| (1..=max_distance).contains(distance))
+        .collect();
+    close.sort();
+    close
+        .into_iter()
+        .take(SIMILAR_MAX)
+        .map(|(_, candidate)| candidate.clone())
+        .collect()
+}
+
+// Existing extensions with an ID similar to the given one, for "did you mean".
+// The name is searched first; a misspelled name may match nothing, so the
+// extensions of the publisher are searched next.
+pub async fn similar(client: &HttpClient, query_url: &str, id: &str, verbose: bool) -> Vec<String> {
+    let (publisher, name) = id.split_once('.').unwrap_or(("", id));
+    for text in [name.replace(['-', '_'], " "), publisher.to_string()] {
+        if text.is_empty() {
+            continue;
+        }
+        let query = Query {
+            text: &text,
+            page: 1,
+            page_size: SIMILAR_PAGE_SIZE,
+            sort_by: SORT_RELEVANCE,
+            category: None,
+            tags: &[],
+        };
+        match search(client, query_url, &query, verbose).await {
+            Ok(results) => {
+                let ids: Vec<String> = results
+                    .results
+                    .into_iter()
+                    .map(|result| result.id)
+                    .collect();
+                let suggestions = closest(id, &ids);
+                if !suggestions.is_empty() {
+                    return suggestions;
+                }
+            }
+            Err(e) => {
+                if verbose {
+                    println!("Failed to search for similar extensions: {e}");
+                }
+                break;
+            }
+        }
+    }
+    Vec::new()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_payload() {
+        let tags = vec!["yaml".to_string()];
+        let query = Query {
+            text: "yaml",
+            page: 2,
+            page_size: 10,
+            sort_by: SORT_INSTALLS,
+            category: Some("Linters"),
+            tags: &tags,
+        };
+        let payload = payload(&query);
+        let filter = &payload["filters"][0];
+        assert_eq!(filter["pageNumber"], 2);
+        assert_eq!(filter["pageSize"], 10);
+        assert_eq!(filter["sortBy"], SORT_INSTALLS);
+        let criteria = filter["criteria"].as_array().unwrap();
+        for (filter_type, value) in [
+            (FILTER_SEARCH_TEXT, "yaml"),
+            (FILTER_CATEGORY, "Linters"),
+            (FILTER_TAG, "yaml"),
+        ] {
+            assert!(criteria
+                .iter()
+                .any(|c| c["filterType"] == filter_type && c["value"] == value));
+        }
+    }
+
+    #[test]
+    fn test_closest() {
+        let candidates: Vec<String> = [
+            "ms-python.pylint",
+            "ms-python.python",
+            "ms-toolsai.jupyter",
+            "ms-python.pyhton-tools",
+        ]
+        .iter()
+        .map(|id| id.to_string())
+        .collect();
+        assert_eq!(distance("ms-python.pyhton", "ms-python.python"), 2);
+        assert_eq!(
+            closest("ms-python.pyhton", &candidates),
+            vec!["ms-python.python"]
+        );
+        assert_eq!(
+            closest("MS-Python.Pylnt", &candidates),
+            vec!["ms-python.pylint"]
+        );
+        assert!(closest("a.b", &candidates).is_empty());
+    }
+
+    #[test]
+    fn test_parse_results() {
+        let response_json = json!({
+            "results": [{
+                "extensions": [
+                    {
+                        "publisher": {"publisherName": "redhat", "displayName": "Red Hat"},
+                        "extensionName": "vscode-yaml",
+                        "displayName": "YAML",
+                        "versions": [{"version": "1.14.0"}],
+                        "statistics": [{"statisticName": "install", "value": 100.0}]
+                    },
+                    {"extensionName": "no-publisher"}
+                ],
+                "resultMetadata": [{
+                    "metadataType": "ResultCount",
+                    "metadataItems": [{"name": "TotalCount", "count": 42}]
+                }]
+            }]
+        });
+        let results = parse_results(&response_json).unwrap();
+        assert_eq!(results.total, 42);
+        assert_eq!(results.results.len(), 1);
+        let result = &results.results[0];
+        assert_eq!(result.id, "redhat.vscode-yaml");
+        assert_eq!(result.version, "1.14.0");
+        assert_eq!(result.metadata.display_name.as_deref(), Some("YAML"));
+        assert_eq!(result.metadata.install_count, Some(100));
+    }
+}
@@ -0,0 +1,165 @@
+use base64::engine::general_purpose::STANDARD;
+use base64::Engine;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::{HashMap, HashSet};
+use std::error::Error;
+use std::io::{Cursor, Read};
+
+pub const ASSET_TYPE: &str = "Microsoft.VisualStudio.Services.VsixSignature";
+pub const MANIFEST_ENTRY: &str = ".signature.manifest";
+pub const SIGNATURE_ENTRY: &str = ".signature.p7s";
+
+// Content of .signature.manifest in the .sigzip archive
+#[derive(Deserialize, Debug)]
+pub struct SignatureManifest {
+    pub package: EntryDigest,
+    // Key is the path of the entry in the package, base64 encoded
+    pub entries: HashMap<String, EntryDigest>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct EntryDigest {
+    pub size: u64,
+    pub digests: HashMap<String, String>,
+}
+
+impl EntryDigest {
+    fn check(&self, what: &str, content: &[u8]) -> Result<(), Box<dyn Error>> {
+        if self.size != content.len() as u64 {
+            return Err(Box::from(format!(
+                "Size of {what} does not match the signature manifest"
+            )));
+        }
+        let expected = self
+            .digests
+            .get("sha256")
+            .ok_or_else(|| format!("No sha256 digest for {what} in the signature manifest"))?;
+        if *expected != STANDARD.encode(Sha256::digest(content)) {
+            return Err(Box::from(format!(
+                "Digest of {what} does not match the signature manifest"
+            )));
+        }
+        Ok(())
+    }
+}
+
+// The file VS Code looks for next to a VSIX when verifying it offline
+pub fn file_name(vsix_file_name: &str) -> String {
+    let base_name = vsix_file_name
+        .strip_suffix(".vsix")
+        .unwrap_or(vsix_file_name);
+    format!("{base_name}.sigzip")
+}
+
+fn entry_path(key: &str) -> String {
+    STANDARD
+        .decode(key)
+        .ok()
+        .and_then(|decoded| String::from_utf8(decoded).ok())
+        .unwrap_or_else(|| key.to_string())
+}
+
+pub fn read_manifest(sigzip: &[u8]) -> Result<SignatureManifest, Box<dyn Error>> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(sigzip))
+        .map_err(|e| format!("Not a valid signature archive: {e}"))?;
+    if archive.by_name(SIGNATURE_ENTRY).is_err() {
+        return Err(Box::from(format!("{SIGNATURE_ENTRY} is missing")));
+    }
+    let mut manifest = String::new();
+    archive
+        .by_name(MANIFEST_ENTRY)
+        .map_err(|_| format!("{MANIFEST_ENTRY} is missing"))?
+        .read_to_string(&mut manifest)?;
+    Ok(serde_json::from_str(&manifest)?)
+}
+
+// Check the digests of the signature manifest against the package contents.
+// The PKCS#7 signature over the manifest itself is verified by VS Code
+// when the extension is installed together with the .sigzip file.
+pub fn verify(vsix: &[u8], sigzip: &[u8]) -> Result<(), Box<dyn Error>> {
+    let manifest = read_manifest(sigzip)?;
+    manifest.package.check("the package", vsix)?;
+
+    let mut archive = zip::ZipArchive::new(Cursor::new(vsix))?;
+    let mut signed_paths = HashSet::new();
+    for (key, digest) in &manifest.entries {
+        let path = entry_path(key);
+        let mut content = Vec::new();
+        archive
+            .by_name(&path)
+            .map_err(|_| format!("Signed entry {path} is missing in the package"))?
+            .read_to_end(&mut content)?;
+        digest.check(&path, &content)?;
+        signed_paths.insert(path);
+    }
+    for path in archive.file_names() {
+        if !path.ends_with('/') && !signed_paths.contains(path) {
+            return Err(Box::from(format!(
+                "Entry {path} is not covered by the signature"
+            )));
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::extensions::vsix::tests::{package, vsix};
+
+    fn digest(content: &[u8]) -> serde_json::Value {
+        serde_json::json!({
+            "size": content.len(),
+            "digests": {"sha256": STANDARD.encode(Sha256::digest(content))}
+        })
+    }
+
+    pub fn sigzip(vsix_content: &[u8]) -> Vec<u8> {
+        let mut archive = zip::ZipArchive::new(Cursor::new(vsix_content)).unwrap();
+        let mut entries = serde_json::Map::new();
+        for index in 0..archive.len() {
+            let mut file = archive.by_index(index).unwrap();
+            let mut content = Vec::new();
+            file.read_to_end(&mut content).unwrap();
+            entries.insert(STANDARD.encode(file.name()), digest(&content));
+        }
+        let manifest = serde_json::json!({
+            "package": digest(vsix_content),
+            "entries": entries,
+        });
+        package(&[
+            (MANIFEST_ENTRY, &manifest.to_string()),
+            (SIGNATURE_ENTRY, "signature"),
+        ])
+    }
+
+    #[test]
+    fn test_file_name() {
+        assert_eq!(
+            file_name("publisher.name-1.0.0@linux-x64.vsix"),
+            "publisher.name-1.0.0@linux-x64.sigzip"
+        );
+    }
+
+    #[test]
+    fn test_verify() {
+        let content = vsix("publisher", "name", "1.0.0", None);
+        assert!(verify(&content, &sigzip(&content)).is_ok());
+    }
+
+    #[test]
+    fn test_verify_other_package() {
+        let content = vsix("publisher", "name", "1.0.0", None);
+        let other = vsix("publisher", "name", "1.0.1", None);
+        assert!(verify(&other, &sigzip(&content)).is_err());
+    }
+
+    #[test]
+    fn test_verify_missing_signature() {
+        let content = vsix("publisher", "name", "1.0.0", None);
+        let without_p7s = package(&[(MANIFEST_ENTRY, "{}")]);
+        assert!(verify(&content, &without_p7s).is_err());
+        assert!(verify(&content, b"not a zip").is_err());
+    }
+}
@@ -1,28 +1,125 @@
-pub fn for_download(
-    publisher: &str,
-    extension_name: &str,
-    version: &str,
-    target_platform: Option<String>,
-) -> String {
-    match target_platform {
-        Some(platform) => format!(
-            "https://marketplace.visualstudio.com/_apis/public/gallery/publishers/{publisher}/vsextensions/{extension_name}/{version}/vspackage?targetPlatform={platform}",
-        ),
-        None => format!(
-            "https://marketplace.visualstudio.com/_apis/public/gallery/publishers/{publisher}/vsextensions/{extension_name}/{version}/vspackage",
-        ),
+pub const BASE_URL: &str = "https://marketplace.visualstudio.com/_apis/public/gallery/";
+
+// Placeholders of the asset url template
+const PLACEHOLDERS: [&str; 4] = ["publisher", "name", "version", "platform"];
+const REQUIRED_PLACEHOLDERS: [&str; 3] = ["publisher", "name", "version"];
+// Used for {platform} when the extension is platform independent
+const UNIVERSAL: &str = "universal";
+
+// Endpoints of a gallery-compatible marketplace (the public one or a mirror)
+#[derive(Debug, Clone, PartialEq)]
+pub struct Gallery {
+    // Without a trailing slash
+    base_url: String,
+    // e.g. https://mirror.example.com/vsix/{publisher}/{name}/{version}/{platform}
+    asset_template: Option<String>,
+}
+
+impl Default for Gallery {
+    fn default() -> Self {
+        Gallery {
+            base_url: BASE_URL.trim_end_matches('/').to_string(),
+            asset_template: None,
+        }
     }
 }
 
-pub const BASE_URL: &str = "https://marketplace.visualstudio.com/_apis/public/gallery/";
+fn check_http_url(url: &str, what: &str) -> Result<(), String> {
+    match reqwest::Url::parse(url) {
+        Ok(parsed) if parsed.scheme() == "http" || parsed.scheme() == "https" => Ok(()),
+        Ok(parsed) => Err(format!(
+            "Invalid {what} {url}: unsupported scheme {}",
+            parsed.scheme()
+        )),
+        Err(e) => Err(format!("Invalid {what} {url}: {e}")),
+    }
+}
 
-pub fn query_url() -> String {
-    format!("{BASE_URL}/extensionquery")
+fn placeholders(template: &str) -> Result<Vec<&str>, String> {
+    let mut found = Vec::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        let end = rest[start..]
+            .find('}')
+            .ok_or_else(|| format!("Unclosed placeholder in asset url template {template}"))?;
+        let placeholder = &rest[start + 1..start + end];
+        if !PLACEHOLDERS.contains(&placeholder) {
+            return Err(format!(
+                "Unknown placeholder {{{placeholder}}} in asset url template {template}"
+            ));
+        }
+        found.push(placeholder);
+        rest = &rest[start + end + 1..];
+    }
+    Ok(found)
+}
+
+impl Gallery {
+    // Validate the overrides, so a typo fails at startup rather than on every download
+    pub fn new(base_url: &str, asset_template: Option<&str>) -> Result<Gallery, String> {
+        check_http_url(base_url, "gallery url")?;
+        if let Some(template) = asset_template {
+            let found = placeholders(template)?;
+            for required in REQUIRED_PLACEHOLDERS {
+                if !found.contains(&required) {
+                    return Err(format!(
+                        "Asset url template {template} must contain {{{required}}}"
+                    ));
+                }
+            }
+            let sample = fill(template, "publisher", "name", "1.0.0", UNIVERSAL);
+            check_http_url(&sample, "asset url template")?;
+        }
+        Ok(Gallery {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            asset_template: asset_template.map(|s| s.to_string()),
+        })
+    }
+
+    pub fn query_url(&self) -> String {
+        format!("{}/extensionquery", self.base_url)
+    }
+
+    pub fn for_download(
+        &self,
+        publisher: &str,
+        extension_name: &str,
+        version: &str,
+        target_platform: Option<String>,
+    ) -> String {
+        if let Some(template) = &self.asset_template {
+            let platform = target_platform.as_deref().unwrap_or(UNIVERSAL);
+            return fill(template, publisher, extension_name, version, platform);
+        }
+        let package_url = format!(
+            "{}/publishers/{publisher}/vsextensions/{extension_name}/{version}/vspackage",
+            self.base_url
+        );
+        match target_platform {
+            Some(platform) => format!("{package_url}?targetPlatform={platform}"),
+            None => package_url,
+        }
+    }
+}
+
+fn fill(
+    template: &str,
+    publisher: &str,
+    extension_name: &str,
+    version: &str,
+    platform: &str,
+) -> String {
+    template
+        .replace("{publisher}", publisher)
+        .replace("{name}", extension_name)
+        .replace("{version}", version)
+        .replace("{platform}", platform)
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use rstest::rstest;
 
     #[test]
     fn test_for_download_no_target_platform() {
@@ -34,7 +131,7 @@ mod tests {
             "https://marketplace.visualstudio.com/_apis/public/gallery/publishers/{publisher}/vsextensions/{extension_name}/{version}/vspackage",
         );
         assert_eq!(
-            for_download(publisher, extension_name, version, target_platform),
+            Gallery::default().for_download(publisher, extension_name, version, target_platform),
             expected
         );
     }
@@ -50,14 +147,76 @@ mod tests {
             "https://marketplace.visualstudio.com/_apis/public/gallery/publishers/{publisher}/vsextensions/{extension_name}/{version}/vspackage?targetPlatform={unwraped_target_platform}",
         );
         assert_eq!(
-            for_download(publisher, extension_name, version, target_platform),
+            Gallery::default().for_download(publisher, extension_name, version, target_platform),
             expected
         );
     }
 
+    #[test]
+    fn test_gallery_base_url() {
+        let gallery = Gallery::new("https://mirror.example.com/gallery", None).unwrap();
+        assert_eq!(
+            gallery.query_url(),
+            "https://mirror.example.com/gallery/extensionquery"
+        );
+        assert_eq!(
+            gallery.for_download("publisher", "name", "1.0.0", Some("linux-x64".to_string())),
+            "https://mirror.example.com/gallery/publishers/publisher/vsextensions/name/1.0.0/vspackage?targetPlatform=linux-x64"
+        );
+    }
+
+    #[rstest]
+    #[case(None, "https://mirror.example.com/vsix/publisher/name/1.0.0/universal.vsix")]
+    #[case(
+        Some("linux-x64"),
+        "https://mirror.example.com/vsix/publisher/name/1.0.0/linux-x64.vsix"
+    )]
+    fn test_gallery_asset_template(#[case] platform: Option<&str>, #[case] expected: &str) {
+        let gallery = Gallery::new(
+            BASE_URL,
+            Some("https://mirror.example.com/vsix/{publisher}/{name}/{version}/{platform}.vsix"),
+        )
+        .unwrap();
+        assert_eq!(
+            gallery.for_download("publisher", "name", "1.0.0", platform.map(|p| p.to_string())),
+            expected
+        );
+    }
+
+    #[rstest]
+    #[case("not a url", None)]
+    #[case("ftp://mirror.example.com/gallery", None)]
+    #[case(BASE_URL, Some("https://mirror.example.com/{publisher}/{name}"))]
+    #[case(
+        BASE_URL,
+        Some("https://mirror.example.com/{publisher}/{name}/{version}/{arch}")
+    )]
+    #[case(BASE_URL, Some("https://mirror.example.com/{publisher}/{name}/{version"))]
+    #[case(BASE_URL, Some("{publisher}/{name}/{version}"))]
+    fn test_gallery_invalid(#[case] base_url: &str, #[case] template: Option<&str>) {
+        assert!(Gallery::new(base_url, template).is_err());
+    }
+
     #[test]
     fn test_query_url() {
-        let expected = format!("{BASE_URL}/extensionquery");
-        assert_eq!(query_url(), expected);
+        assert_eq!(
+            Gallery::default().query_url(),
+            "https://marketplace.visualstudio.com/_apis/public/gallery/extensionquery"
+        );
+    }
+
+    #[rstest]
+    #[case("https://mirror.example.com/_apis/public/gallery")]
+    #[case("https://mirror.example.com/_apis/public/gallery/")]
+    fn test_query_url_mirror(#[case] base_url: &str) {
+        let gallery = Gallery::new(base_url, None).unwrap();
+        assert_eq!(
+            gallery.query_url(),
+            "https://mirror.example.com/_apis/public/gallery/extensionquery"
+        );
+        assert_eq!(
+            gallery.for_download("publisher", "name", "1.0.0", None),
+            "https://mirror.example.com/_apis/public/gallery/publishers/publisher/vsextensions/name/1.0.0/vspackage"
+        );
     }
 }
\ No newline at end of file
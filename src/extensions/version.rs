@@ -1,12 +1,19 @@
+use crate::error::Error;
 use std::cmp::Ordering;
 
-#[derive(Debug, PartialEq, Eq, PartialOrd)]
+#[derive(Debug, PartialEq, Eq)]
 pub struct Version {
     major: u32,
     minor: u32,
     patch: u32,
 }
 
+impl PartialOrd for Version {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
 impl Ord for Version {
     fn cmp(&self, other: &Self) -> Ordering {
         if self.major != other.major {
@@ -19,20 +26,29 @@ impl Ord for Version {
     }
 }
 
-pub fn parse(version: &str) -> Version {
+pub fn parse(version: &str) -> Result<Version, Error> {
     let parts: Vec<&str> = version.split('.').collect();
-    if parts.len() != 3 {
-        eprintln!("Invalid version format: {version}");
-        panic!("Version must be in the format 'major.minor.patch'");
-    }
-    let major = parts[0].parse().unwrap();
-    let minor = parts[1].parse().unwrap();
-    let patch = parts[2].parse().unwrap();
-    Version {
-        major,
-        minor,
-        patch,
+    let numbers: Vec<u32> = parts.iter().filter_map(|part| part.parse().ok()).collect();
+    if parts.len() != 3 || numbers.len() != 3 {
+        return Err(Error::InvalidVersion(version.to_string()));
     }
+    Ok(Version {
+        major: numbers[0],
+        minor: numbers[1],
+        patch: numbers[2],
+    })
+}
+
+// Order by the numeric parts of the version, so that 1.10.0 is newer than 1.9.0.
+// Unlike parse, this accepts any version, e.g. of local files or other registries.
+pub fn compare(a: &str, b: &str) -> Ordering {
+    let key = |version: &str| -> Vec<u64> {
+        version
+            .split(['.', '-', '+'])
+            .map(|part| part.parse().unwrap_or(0))
+            .collect()
+    };
+    key(a).cmp(&key(b))
 }
 
 #[cfg(test)]
@@ -59,8 +75,26 @@ mod tests {
         #[case] left_version: &str,
         #[case] expected: Ordering,
     ) {
-        let right = parse(right_version);
-        let left = parse(left_version);
+        let right = parse(right_version).unwrap();
+        let left = parse(left_version).unwrap();
         assert_eq!(right.cmp(&left), expected);
     }
+
+    #[rstest]
+    #[case("1.10.0", "1.9.0", Ordering::Greater)]
+    #[case("2024.1.5", "2024.1.10", Ordering::Less)]
+    #[case("1.0.0", "1.0", Ordering::Greater)]
+    #[case("1.2.3", "1.2.3", Ordering::Equal)]
+    fn test_compare(#[case] a: &str, #[case] b: &str, #[case] expected: Ordering) {
+        assert_eq!(compare(a, b), expected);
+    }
+
+    #[rstest]
+    #[case("1.2")]
+    #[case("1.2.3.4")]
+    #[case("1.2.3-beta")]
+    #[case("one.two.three")]
+    fn test_invalid(#[case] version: &str) {
+        assert!(matches!(parse(version), Err(Error::InvalidVersion(v)) if v == version));
+    }
 }
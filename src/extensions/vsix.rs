@@ -0,0 +1,250 @@
+use std::error::Error;
+use std::io::{Cursor, Read};
+
+pub const MANIFEST_ENTRY: &str = "extension.vsixmanifest";
+pub const PACKAGE_JSON_ENTRY: &str = "extension/package.json";
+pub const ENGINE_PROPERTY: &str = "Microsoft.VisualStudio.Code.Engine";
+pub const PRE_RELEASE_PROPERTY: &str = "Microsoft.VisualStudio.Code.PreRelease";
+
+// Identity of a package, read from extension.vsixmanifest
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Manifest {
+    pub publisher: String,
+    pub name: String,
+    pub version: String,
+    pub target_platform: Option<String>,
+    // VS Code version range the package requires, e.g. ^1.80.0
+    pub engine: Option<String>,
+    pub pre_release: bool,
+}
+
+pub fn parse_manifest(xml: &str) -> Result<Manifest, Box<dyn Error>> {
+    let document = roxmltree::Document::parse(xml)?;
+    let identity = document
+        .descendants()
+        .find(|node| node.has_tag_name("Identity"))
+        .ok_or("Identity element is missing in the manifest")?;
+    let attribute = |name: &str| -> Result<String, Box<dyn Error>> {
+        identity
+            .attribute(name)
+            .map(|value| value.to_string())
+            .ok_or_else(|| Box::from(format!("Identity {name} is missing in the manifest")))
+    };
+    let property = |id: &str| {
+        document
+            .descendants()
+            .find(|node| node.has_tag_name("Property") && node.attribute("Id") == Some(id))
+            .and_then(|node| node.attribute("Value"))
+    };
+    Ok(Manifest {
+        publisher: attribute("Publisher")?,
+        name: attribute("Id")?,
+        version: attribute("Version")?,
+        target_platform: identity
+            .attribute("TargetPlatform")
+            .filter(|platform| !platform.is_empty() && *platform != "universal")
+            .map(|platform| platform.to_string()),
+        engine: property(ENGINE_PROPERTY).map(|engine| engine.to_string()),
+        pre_release: property(PRE_RELEASE_PROPERTY) == Some("true"),
+    })
+}
+
+// Open the archive, check the required entries and read the manifest
+pub fn read_manifest(content: &[u8]) -> Result<Manifest, Box<dyn Error>> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(content))
+        .map_err(|e| format!("Not a valid VSIX (ZIP) package: {e}"))?;
+    if archive.by_name(PACKAGE_JSON_ENTRY).is_err() {
+        return Err(Box::from(format!("{PACKAGE_JSON_ENTRY} is missing")));
+    }
+    let mut xml = String::new();
+    archive
+        .by_name(MANIFEST_ENTRY)
+        .map_err(|_| format!("{MANIFEST_ENTRY} is missing"))?
+        .read_to_string(&mut xml)?;
+    parse_manifest(&xml)
+}
+
+// Content of a single entry in the package, e.g. extension/package.json
+pub fn read_entry(content: &[u8], name: &str) -> Result<Vec<u8>, Box<dyn Error>> {
+    let mut archive = zip::ZipArchive::new(Cursor::new(content))
+        .map_err(|e| format!("Not a valid VSIX (ZIP) package: {e}"))?;
+    let mut entry = archive
+        .by_name(name)
+        .map_err(|_| format!("{name} is missing"))?;
+    let mut data = Vec::new();
+    entry.read_to_end(&mut data)?;
+    Ok(data)
+}
+
+// Check that the package is the requested extension, version and platform
+pub fn validate(
+    content: &[u8],
+    publisher: &str,
+    extension_name: &str,
+    version: &str,
+    target_platform: Option<&str>,
+) -> Result<Manifest, Box<dyn Error>> {
+    let manifest = read_manifest(content)?;
+    let requested = format!("{publisher}.{extension_name}");
+    let found = format!("{}.{}", manifest.publisher, manifest.name);
+    if !requested.eq_ignore_ascii_case(&found) {
+        return Err(Box::from(format!(
+            "Package identity mismatch: requested {requested}, found {found}"
+        )));
+    }
+    if manifest.version != version {
+        return Err(Box::from(format!(
+            "Package version mismatch: requested {version}, found {}",
+            manifest.version
+        )));
+    }
+    if manifest.target_platform.as_deref() != target_platform {
+        return Err(Box::from(format!(
+            "Package target platform mismatch: requested {}, found {}",
+            target_platform.unwrap_or("universal"),
+            manifest.target_platform.as_deref().unwrap_or("universal")
+        )));
+    }
+    Ok(manifest)
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use rstest::rstest;
+    use std::io::Write;
+    use zip::write::SimpleFileOptions;
+
+    pub(crate) fn manifest_xml(
+        publisher: &str,
+        name: &str,
+        version: &str,
+        target_platform: Option<&str>,
+    ) -> String {
+        let target_platform = target_platform
+            .map(|platform| format!(r#" TargetPlatform="{platform}""#))
+            .unwrap_or_default();
+        format!(
+            r#"<?xml version="1.0" encoding="utf-8"?>
+<PackageManifest Version="2.0.0" xmlns="http://schemas.microsoft.com/developer/vsx-schema/2011">
+  <Metadata>
+    <Identity Language="en-US" Id="{name}" Version="{version}" Publisher="{publisher}"{target_platform}/>
+    <DisplayName>{name}</DisplayName>
+  </Metadata>
+</PackageManifest>"#
+        )
+    }
+
+    pub(crate) fn package(entries: &[(&str, &str)]) -> Vec<u8> {
+        let mut writer = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        for (name, content) in entries {
+            writer
+                .start_file(*name, SimpleFileOptions::default())
+                .unwrap();
+            writer.write_all(content.as_bytes()).unwrap();
+        }
+        writer.finish().unwrap().into_inner()
+    }
+
+    pub(crate) fn vsix(
+        publisher: &str,
+        name: &str,
+        version: &str,
+        target_platform: Option<&str>,
+    ) -> Vec<u8> {
+        package(&[
+            (
+                MANIFEST_ENTRY,
+                &manifest_xml(publisher, name, version, target_platform),
+            ),
+            (PACKAGE_JSON_ENTRY, "{}"),
+        ])
+    }
+
+    #[test]
+    fn test_read_manifest() {
+        let content = vsix("rust-lang", "rust-analyzer", "0.4.2304", Some("linux-x64"));
+        let manifest = read_manifest(&content).unwrap();
+        assert_eq!(
+            manifest,
+            Manifest {
+                publisher: "rust-lang".to_string(),
+                name: "rust-analyzer".to_string(),
+                version: "0.4.2304".to_string(),
+                target_platform: Some("linux-x64".to_string()),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_parse_manifest_properties() {
+        let xml = r#"<?xml version="1.0" encoding="utf-8"?>
+<PackageManifest Version="2.0.0" xmlns="http://schemas.microsoft.com/developer/vsx-schema/2011">
+  <Metadata>
+    <Identity Language="en-US" Id="name" Version="1.1.0" Publisher="publisher"/>
+    <Properties>
+      <Property Id="Microsoft.VisualStudio.Code.Engine" Value="^1.80.0" />
+      <Property Id="Microsoft.VisualStudio.Code.PreRelease" Value="true" />
+    </Properties>
+  </Metadata>
+</PackageManifest>"#;
+        let manifest = parse_manifest(xml).unwrap();
+        assert_eq!(manifest.engine.as_deref(), Some("^1.80.0"));
+        assert!(manifest.pre_release);
+        let manifest = parse_manifest(&manifest_xml("publisher", "name", "1.0.0", None)).unwrap();
+        assert_eq!(manifest.engine, None);
+        assert!(!manifest.pre_release);
+    }
+
+    #[rstest]
+    #[case("rust-lang", "rust-analyzer", "0.4.2304", Some("linux-x64"))]
+    #[case("Rust-Lang", "Rust-Analyzer", "0.4.2304", Some("linux-x64"))]
+    fn test_validate_ok(
+        #[case] publisher: &str,
+        #[case] name: &str,
+        #[case] version: &str,
+        #[case] target_platform: Option<&str>,
+    ) {
+        let content = vsix("rust-lang", "rust-analyzer", "0.4.2304", Some("linux-x64"));
+        assert!(validate(&content, publisher, name, version, target_platform).is_ok());
+    }
+
+    #[rstest]
+    #[case("other", "rust-analyzer", "0.4.2304", Some("linux-x64"))]
+    #[case("rust-lang", "rust-analyzer", "0.4.2305", Some("linux-x64"))]
+    #[case("rust-lang", "rust-analyzer", "0.4.2304", Some("win32-x64"))]
+    #[case("rust-lang", "rust-analyzer", "0.4.2304", None)]
+    fn test_validate_mismatch(
+        #[case] publisher: &str,
+        #[case] name: &str,
+        #[case] version: &str,
+        #[case] target_platform: Option<&str>,
+    ) {
+        let content = vsix("rust-lang", "rust-analyzer", "0.4.2304", Some("linux-x64"));
+        assert!(validate(&content, publisher, name, version, target_platform).is_err());
+    }
+
+    #[test]
+    fn test_validate_universal() {
+        let content = vsix("publisher", "name", "1.0.0", None);
+        assert!(validate(&content, "publisher", "name", "1.0.0", None).is_ok());
+        let content = vsix("publisher", "name", "1.0.0", Some("universal"));
+        assert!(validate(&content, "publisher", "name", "1.0.0", None).is_ok());
+    }
+
+    #[test]
+    fn test_not_a_zip() {
+        let html = b"<html><body>Service Unavailable</body></html>";
+        assert!(read_manifest(html).is_err());
+    }
+
+    #[test]
+    fn test_missing_entries() {
+        let manifest = manifest_xml("publisher", "name", "1.0.0", None);
+        let without_package_json = package(&[(MANIFEST_ENTRY, &manifest)]);
+        assert!(read_manifest(&without_package_json).is_err());
+        let without_manifest = package(&[(PACKAGE_JSON_ENTRY, "{}")]);
+        assert!(read_manifest(&without_manifest).is_err());
+    }
+}
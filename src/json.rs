@@ -4,3 +4,347 @@ use serde::Deserialize;
 pub struct Extensions {
     pub recommendations: Vec<String>,
 }
+
+const RECOMMENDATIONS_KEY: &str = "\"recommendations\"";
+const DEFAULT_INDENT: &str = "    ";
+
+// A significant token of a JSON with comments (JSONC) document, as byte offsets
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Token {
+    Punct(u8, usize),
+    Value(usize, usize),
+}
+
+impl Token {
+    fn start(&self) -> usize {
+        match self {
+            Token::Punct(_, start) => *start,
+            Token::Value(start, _) => *start,
+        }
+    }
+
+    fn end(&self) -> usize {
+        match self {
+            Token::Punct(_, start) => start + 1,
+            Token::Value(_, end) => *end,
+        }
+    }
+}
+
+// Split the document into punctuation and values, skipping whitespace and comments
+fn tokenize(content: &str) -> Result<Vec<Token>, String> {
+    let bytes = content.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'/' if bytes.get(i + 1) == Some(&b'/') => {
+                while i < bytes.len() && bytes[i] != b'\n' {
+                    i += 1;
+                }
+            }
+            b'/' if bytes.get(i + 1) == Some(&b'*') => {
+                let end = content[i + 2..].find("*/").ok_or("Unterminated comment")?;
+                i += end + 4;
+            }
+            b'"' => {
+                let start = i;
+                i += 1;
+                while i < bytes.len() && bytes[i] != b'"' {
+                    i += if bytes[i] == b'\\' { 2 } else { 1 };
+                }
+                if i >= bytes.len() {
+                    return Err("Unterminated string".to_string());
+                }
+                i += 1;
+                tokens.push(Token::Value(start, i));
+            }
+            b'{' | b'}' | b'[' | b']' | b',' | b':' => {
+                tokens.push(Token::Punct(bytes[i], i));
+                i += 1;
+            }
+            byte if byte.is_ascii_whitespace() => i += 1,
+            _ => {
+                let start = i;
+                while i < bytes.len() && !b"{}[],:/\" \t\r\n".contains(&bytes[i]) {
+                    i += 1;
+                }
+                tokens.push(Token::Value(start, i));
+            }
+        }
+    }
+    Ok(tokens)
+}
+
+// The document as plain JSON: comments and trailing commas are blanked out, keeping the
+// line breaks so that errors still point at the right line of the file
+fn strip_jsonc(content: &str) -> Result<String, String> {
+    let tokens = tokenize(content)?;
+    let mut plain: Vec<u8> = content
+        .bytes()
+        .map(|byte| if byte == b'\n' { byte } else { b' ' })
+        .collect();
+    for (index, token) in tokens.iter().enumerate() {
+        let trailing_comma = matches!(token, Token::Punct(b',', _))
+            && matches!(tokens.get(index + 1), Some(Token::Punct(b']' | b'}', _)));
+        if !trailing_comma {
+            plain[token.start()..token.end()]
+                .copy_from_slice(&content.as_bytes()[token.start()..token.end()]);
+        }
+    }
+    // Tokens start and end at ASCII characters, everything else became ASCII
+    String::from_utf8(plain).map_err(|e| e.to_string())
+}
+
+// Read an extensions.json, which VS Code writes as JSON with comments
+pub fn parse_extensions(content: &str) -> Result<Extensions, String> {
+    serde_json::from_str(&strip_jsonc(content)?).map_err(|e| e.to_string())
+}
+
+// Leading whitespace of the line the offset is on
+fn line_indent(content: &str, offset: usize) -> &str {
+    let line_start = content[..offset].rfind('\n').map_or(0, |i| i + 1);
+    let line = &content[line_start..];
+    &line[..line.len() - line.trim_start().len()]
+}
+
+fn same_line(content: &str, a: usize, b: usize) -> bool {
+    !content[a.min(b)..a.max(b)].contains('\n')
+}
+
+fn quote(id: &str) -> String {
+    serde_json::Value::String(id.to_string()).to_string()
+}
+
+// Add extension IDs to the recommendations of an extensions.json, keeping the comments
+// and the formatting of the rest of the file. IDs already recommended (in any case)
+// are skipped. Returns the new content and the IDs that were added.
+pub fn add_recommendations(content: &str, ids: &[String]) -> Result<(String, Vec<String>), String> {
+    let tokens = tokenize(content)?;
+    let punct = |index: usize, expected: u8| matches!(tokens.get(index), Some(Token::Punct(c, _)) if *c == expected);
+    if !punct(0, b'{') {
+        return Err("extensions.json must contain an object".to_string());
+    }
+
+    // Find "recommendations": [ among the members of the root object
+    let mut depth = 0;
+    let mut array = None;
+    for (index, token) in tokens.iter().enumerate() {
+        match token {
+            Token::Punct(b'{' | b'[', _) => depth += 1,
+            Token::Punct(b'}' | b']', _) => depth -= 1,
+            Token::Value(start, end)
+                if depth == 1 && &content[*start..*end] == RECOMMENDATIONS_KEY =>
+            {
+                if !punct(index + 1, b':') || !punct(index + 2, b'[') {
+                    return Err("recommendations must be an array".to_string());
+                }
+                array = Some(index + 2);
+                break;
+            }
+            _ => {}
+        }
+    }
+
+    let Some(open) = array else {
+        // No recommendations yet, add the member at the top of the object
+        let added = dedup(ids, &[]);
+        if added.is_empty() {
+            return Ok((content.to_string(), added));
+        }
+        let brace = tokens[0].end();
+        let empty_object = punct(1, b'}');
+        let indent = DEFAULT_INDENT;
+        let items: Vec<String> = added
+            .iter()
+            .map(|id| format!("{indent}{indent}{}", quote(id)))
+            .collect();
+        let member = format!(
+            "\n{indent}{RECOMMENDATIONS_KEY}: [\n{}\n{indent}]{}",
+            items.join(",\n"),
+            if empty_object { "\n" } else { "," }
+        );
+        let rest = if empty_object {
+            content[brace..].trim_start()
+        } else {
+            &content[brace..]
+        };
+        return Ok((format!("{}{member}{rest}", &content[..brace]), added));
+    };
+
+    // The elements of the array and its closing bracket
+    let mut depth = 0;
+    let mut close = None;
+    let mut existing = Vec::new();
+    for (index, token) in tokens.iter().enumerate().skip(open) {
+        match token {
+            Token::Punct(b'{' | b'[', _) => depth += 1,
+            Token::Punct(b'}' | b']', _) => {
+                depth -= 1;
+                if depth == 0 {
+                    close = Some(index);
+                    break;
+                }
+            }
+            Token::Value(start, end) if depth == 1 => {
+                if let Ok(id) = serde_json::from_str::<String>(&content[*start..*end]) {
+                    existing.push(id);
+                }
+            }
+            _ => {}
+        }
+    }
+    let close = close.ok_or("Unterminated recommendations array")?;
+    let added = dedup(ids, &existing);
+    if added.is_empty() {
+        return Ok((content.to_string(), added));
+    }
+
+    let open_offset = tokens[open].start();
+    let close_offset = tokens[close].start();
+    let key_indent = line_indent(content, tokens[open - 2].start());
+    // Insertions as (offset, text), in the order of the offsets
+    let mut edits: Vec<(usize, String)> = Vec::new();
+    let mut replace_to = None;
+    if close == open + 1 {
+        // Empty array, possibly with comments in it
+        let unit = if key_indent.is_empty() {
+            DEFAULT_INDENT
+        } else {
+            key_indent
+        };
+        let items: Vec<String> = added
+            .iter()
+            .map(|id| format!("{key_indent}{unit}{}", quote(id)))
+            .collect();
+        let insert_at = content[..close_offset].trim_end().len();
+        edits.push((insert_at, format!("\n{}\n{key_indent}", items.join(",\n"))));
+        replace_to = Some((insert_at, close_offset));
+    } else {
+        let first = tokens[open + 1];
+        let last = tokens[close - 1];
+        let trailing_comma = matches!(last, Token::Punct(b',', _));
+        let multiline = !same_line(content, open_offset, first.start());
+        let separator = if multiline {
+            format!("\n{}", line_indent(content, first.start()))
+        } else {
+            " ".to_string()
+        };
+        let items: Vec<String> = added
+            .iter()
+            .map(|id| format!("{separator}{}", quote(id)))
+            .collect();
+        // New lines go after a comment at the end of the last element's line
+        let line_end = content[last.end()..close_offset]
+            .find('\n')
+            .map(|i| last.end() + content[last.end()..last.end() + i].trim_end().len())
+            .filter(|_| multiline)
+            .unwrap_or(last.end());
+        if trailing_comma {
+            edits.push((line_end, format!("{},", items.join(","))));
+        } else {
+            edits.push((last.end(), ",".to_string()));
+            edits.push((line_end, items.join(",")));
+        }
+    }
+    let mut updated = content.to_string();
+    for (offset, text) in edits.iter().rev() {
+        match replace_to {
+            Some((from, to)) if from == *offset => updated.replace_range(from..to, text),
+            _ => updated.insert_str(*offset, text),
+        }
+    }
+    Ok((updated, added))
+}
+
+// The IDs that are not recommended yet, without duplicates
+fn dedup(ids: &[String], existing: &[String]) -> Vec<String> {
+    let mut added: Vec<String> = Vec::new();
+    for id in ids {
+        let known = existing
+            .iter()
+            .chain(added.iter())
+            .any(|other| other.eq_ignore_ascii_case(id));
+        if !known {
+            added.push(id.clone());
+        }
+    }
+    added
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use rstest::rstest;
+
+    fn ids(ids: &[&str]) -> Vec<String> {
+        ids.iter().map(|id| id.to_string()).collect()
+    }
+
+    #[rstest]
+    #[case(
+        "{\n\t// See https://go.microsoft.com/fwlink/?LinkId=827846\n\t\"recommendations\": [\n\t\t\"rust-lang.rust-analyzer\" // Rust\n\t]\n}\n",
+        "{\n\t// See https://go.microsoft.com/fwlink/?LinkId=827846\n\t\"recommendations\": [\n\t\t\"rust-lang.rust-analyzer\", // Rust\n\t\t\"redhat.vscode-yaml\"\n\t]\n}\n"
+    )]
+    #[case(
+        "{\n  \"recommendations\": [\n    \"rust-lang.rust-analyzer\",\n  ]\n}",
+        "{\n  \"recommendations\": [\n    \"rust-lang.rust-analyzer\",\n    \"redhat.vscode-yaml\",\n  ]\n}"
+    )]
+    #[case(
+        "{\"recommendations\": [\"rust-lang.rust-analyzer\"]}",
+        "{\"recommendations\": [\"rust-lang.rust-analyzer\", \"redhat.vscode-yaml\"]}"
+    )]
+    #[case(
+        "{\n    \"recommendations\": [\n        /* none yet */\n    ]\n}",
+        "{\n    \"recommendations\": [\n        /* none yet */\n        \"redhat.vscode-yaml\"\n    ]\n}"
+    )]
+    #[case(
+        "{\n    \"unwantedRecommendations\": []\n}",
+        "{\n    \"recommendations\": [\n        \"redhat.vscode-yaml\"\n    ],\n    \"unwantedRecommendations\": []\n}"
+    )]
+    #[case(
+        "{}",
+        "{\n    \"recommendations\": [\n        \"redhat.vscode-yaml\"\n    ]\n}"
+    )]
+    fn test_add_recommendations(#[case] content: &str, #[case] expected: &str) {
+        let (updated, added) =
+            add_recommendations(content, &ids(&["redhat.vscode-yaml", "RedHat.VSCode-YAML"]))
+                .unwrap();
+        assert_eq!(updated, expected);
+        assert_eq!(added, ids(&["redhat.vscode-yaml"]));
+    }
+
+    #[rstest]
+    #[case("{\"recommendations\": [\"a.b\"]}")]
+    #[case(
+        "{\n    // See https://go.microsoft.com/fwlink/?LinkId=827846\n    \"recommendations\": [\n        \"a.b\", // Ä\n        /* \"c.d\", */\n    ],\n}\n"
+    )]
+    #[case("{\"recommendations\": [\"a.b\" /* , */], \"unwantedRecommendations\": [],}")]
+    fn test_parse_extensions(#[case] content: &str) {
+        assert_eq!(
+            parse_extensions(content).unwrap().recommendations,
+            ids(&["a.b"])
+        );
+    }
+
+    #[test]
+    fn test_parse_extensions_error() {
+        let error =
+            parse_extensions("{\n    // comment\n    \"recommendations\": [\"a.b\" \"c.d\"]\n}")
+                .unwrap_err();
+        assert!(error.contains("line 3"), "{error}");
+        assert!(parse_extensions("{\"recommendations\": [\"a.b\"] /* open").is_err());
+        assert!(parse_extensions("{\"recommendations\": [\"a.b\",,]}").is_err());
+    }
+
+    #[test]
+    fn test_add_recommendations_unchanged() {
+        let content = "{\"recommendations\": [\"redhat.vscode-yaml\"]} // end";
+        let (updated, added) = add_recommendations(content, &ids(&["RedHat.VSCode-YAML"])).unwrap();
+        assert_eq!(updated, content);
+        assert!(added.is_empty());
+        assert!(add_recommendations("[]", &ids(&["a.b"])).is_err());
+        assert!(add_recommendations("{\"recommendations\": \"a.b\"}", &ids(&["a.b"])).is_err());
+        assert!(add_recommendations("{/* open", &ids(&["a.b"])).is_err());
+    }
+}
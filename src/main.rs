@@ -1,114 +1,288 @@
-use clap::Parser;
-use env_logger;
+use clap::FromArgMatches;
 use std::error::Error;
 use std::fs;
-use tokio;
+use std::time::Instant;
 use extensions::file;
 use extensions::info as ext_info; // For info command
+use extensions::auth;
+use extensions::client;
+use extensions::registry;
+use extensions::search;
+use extensions::url;
+use extensions::registry::{LocalDirectory, Marketplace, OpenVsx, Registry};
 
+mod checksum;
 mod cli;
+mod config;
 mod directory;
+mod error;
+mod export;
 mod extensions;
 mod json;
+mod output;
+mod report;
+mod serve;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     env_logger::init();
-    let cli = cli::Cli::parse();
+    // Command line > environment > project vsixharvester.toml > user config
+    let config = match config::Config::discover() {
+        Ok(config) => config,
+        Err(e) => {
+            eprintln!("{e}");
+            return Err(Box::from(e));
+        }
+    };
+    let matches = config.command().get_matches();
+    let mut cli = match cli::Cli::from_arg_matches(&matches) {
+        Ok(cli) => cli,
+        Err(e) => e.exit(),
+    };
+    if let Some(cli::Commands::Config(args)) = &cli.command {
+        return match args.command {
+            cli::ConfigCommands::Show => config::show(&config, &matches),
+        };
+    }
 
-    // Global options
-    let proxy = cli.proxy.as_deref();
     let verbose = cli.verbose;
 
     // Determine the command to execute
-    let command_to_execute = match cli.command {
+    let command_to_execute = match cli.command.take() {
         Some(command) => command,
         None => {
             if verbose {
                 println!("No subcommand specified, using top-level options for download.");
             }
             // Use the flattened DownloadArgs from Cli struct
-            cli::Commands::Download(cli.download_defaults)
+            cli::Commands::Download(cli.download_defaults.clone())
         }
     };
 
+    // The client and the registries are built only for the commands that
+    // query registries, so the others do not fail on their options
     match command_to_execute {
         cli::Commands::Download(args) => {
-            handle_download_command(args, proxy, verbose).await?;
+            let registries = build_registries(&cli, verbose)?;
+            let code = handle_download_command(args, verbose, &registries).await?;
+            if code != 0 {
+                std::process::exit(code);
+            }
         }
+        cli::Commands::Verify(args) => {
+            handle_verify_command(args, verbose)?;
+        }
+        cli::Commands::Export(args) => {
+            handle_export_command(args, verbose)?;
+        }
+        cli::Commands::Serve(args) => {
+            serve::run(&args.root, &args.host, args.port, verbose).await?;
+        }
+        cli::Commands::Config(_) => unreachable!("handled above"),
         cli::Commands::Info(args) => {
-            let extensions_to_info: Vec<String>;
+            let registries = build_registries(&cli, verbose)?;
+            handle_info_command(args, verbose, &registries).await?;
+        }
+        cli::Commands::Search(args) => {
+            let client = build_client(&cli, verbose)?;
+            let gallery = build_gallery(&cli)?;
+            handle_search_command(args, verbose, &client, &gallery).await?;
+        }
+    }
+    Ok(())
+}
 
-            if let Some(single_extension_id) = &args.single {
-                if verbose {
-                    println!("Fetching info for single extension: {}", single_extension_id);
-                }
-                extensions_to_info = vec![single_extension_id.clone()];
-            } else {
-                if verbose {
-                    println!("Attempting to read file for info: {}", &args.input);
-                }
-                let file_content = match fs::read_to_string(&args.input) {
-                    Ok(content) => content,
-                    Err(e) => {
-                        eprintln!("Failed to read file {}: {}", &args.input, e);
-                        return Err(Box::new(e) as Box<dyn Error>);
-                    }
-                };
-                let extensions_data: json::Extensions = match serde_json::from_str(&file_content) {
-                    Ok(data) => data,
-                    Err(e) => {
-                        eprintln!("Failed to parse file {}: {}", &args.input, e);
-                        return Err(Box::new(e) as Box<dyn Error>);
-                    }
+// HTTP client with the proxy, TLS and token options
+fn build_client(cli: &cli::Cli, verbose: bool) -> Result<client::HttpClient, Box<dyn Error>> {
+    let mut registry_hosts = Vec::new();
+    for kind in &cli.registry {
+        let urls = match kind {
+            cli::RegistryKind::Msft => {
+                vec![Some(cli.gallery_url.as_str()), cli.gallery_asset_url.as_deref()]
+            }
+            cli::RegistryKind::Openvsx => vec![Some(cli.openvsx_url.as_str())],
+            cli::RegistryKind::Local => vec![],
+        };
+        registry_hosts.extend(urls.into_iter().flatten().filter_map(auth::host));
+    }
+    let credentials = match auth::Credentials::parse(&cli.token, &registry_hosts) {
+        Ok(credentials) => credentials,
+        Err(e) => {
+            eprintln!("{e}");
+            return Err(Box::from(e));
+        }
+    };
+    let client_options = client::ClientOptions {
+        proxy: cli.proxy.as_deref(),
+        proxy_auth: cli.proxy_auth.as_deref(),
+        no_proxy: cli.no_proxy.as_deref(),
+        ca_certs: &cli.ca_cert,
+        no_system_certs: cli.no_system_certs,
+        client_cert: cli.client_cert.as_deref(),
+        client_key: cli.client_key.as_deref(),
+        credentials,
+    };
+    match client::build(client_options, verbose) {
+        Ok(client) => Ok(client),
+        Err(e) => {
+            eprintln!("{e}");
+            Err(e)
+        }
+    }
+}
+
+fn build_gallery(cli: &cli::Cli) -> Result<url::Gallery, Box<dyn Error>> {
+    match url::Gallery::new(&cli.gallery_url, cli.gallery_asset_url.as_deref()) {
+        Ok(gallery) => Ok(gallery),
+        Err(e) => {
+            eprintln!("{e}");
+            Err(Box::from(e))
+        }
+    }
+}
+
+// The registries of --registry in the order they are tried
+fn build_registries(cli: &cli::Cli, verbose: bool) -> Result<Vec<Box<dyn Registry>>, Box<dyn Error>> {
+    let client = build_client(cli, verbose)?;
+    let gallery = build_gallery(cli)?;
+    let mut registries: Vec<Box<dyn Registry>> = Vec::new();
+    for kind in &cli.registry {
+        match kind {
+            cli::RegistryKind::Msft => {
+                registries.push(Box::new(Marketplace::new(gallery.clone(), client.clone(), verbose)))
+            }
+            cli::RegistryKind::Openvsx => {
+                registries.push(Box::new(OpenVsx::new(&cli.openvsx_url, client.clone(), verbose)))
+            }
+            cli::RegistryKind::Local => {
+                let Some(local_dir) = cli.local_dir.as_deref() else {
+                    eprintln!("--registry local requires --local-dir");
+                    return Err(Box::from("--registry local requires --local-dir"));
                 };
-                extensions_to_info = extensions_data.recommendations;
+                registries.push(Box::new(LocalDirectory::new(local_dir, verbose)?))
             }
+        }
+    }
+    Ok(registries)
+}
 
-            for (index, extension_id_str) in extensions_to_info.iter().enumerate() {
-                if verbose {
-                    println!("Processing info for extension: {}", &extension_id_str);
-                }
-                if index > 0 { // Add a separator for multiple extensions
-                    println!("--------------------");
-                }
+async fn handle_info_command(
+    args: cli::InfoArgs,
+    verbose: bool,
+    registries: &[Box<dyn Registry>],
+) -> Result<(), Box<dyn Error>> {
+    let extensions_to_info: Vec<String>;
+
+    if let Some(single_extension_id) = &args.single {
+        if verbose {
+            println!("Fetching info for single extension: {}", single_extension_id);
+        }
+        extensions_to_info = vec![single_extension_id.clone()];
+    } else {
+        if verbose {
+            println!("Attempting to read file for info: {}", &args.input);
+        }
+        let file_content = match fs::read_to_string(&args.input) {
+            Ok(content) => content,
+            Err(e) => {
+                eprintln!("Failed to read file {}: {}", &args.input, e);
+                return Err(Box::new(e) as Box<dyn Error>);
+            }
+        };
+        let extensions_data = match json::parse_extensions(&file_content) {
+            Ok(data) => data,
+            Err(e) => {
+                eprintln!("Failed to parse file {}: {}", &args.input, e);
+                return Err(Box::from(e));
+            }
+        };
+        extensions_to_info = extensions_data.recommendations;
+    }
+
+    if extensions_to_info.len() > 1 {
+        registry::prefetch(registries, &extensions_to_info).await;
+    }
+
+    let mut records = Vec::new();
+    let mut first_error = None;
+    for (index, extension_id_str) in extensions_to_info.iter().enumerate() {
+        if verbose {
+            println!("Processing info for extension: {}", &extension_id_str);
+        }
 
-                let parsed_name = ext_info::parse_extension_name(extension_id_str);
-                match ext_info::get(&parsed_name.publisher, &parsed_name.name, proxy, verbose).await {
-                    Ok(info) => {
-                        println!("Extension: {}.{}", parsed_name.publisher, parsed_name.name);
-                        if info.arch_versions.is_empty() {
-                            println!("  No version information found.");
-                        } else {
-                            println!("  Available versions per platform:");
-                            let mut sorted_versions: Vec<_> = info.arch_versions.iter().collect();
-                            sorted_versions.sort_by_key(|(platform, _)| platform.as_deref().unwrap_or("universal"));
-
-                            for (platform_opt, version) in sorted_versions {
-                                let platform_str = platform_opt.as_deref().unwrap_or("Platform Independent");
-                                println!("    - {}: {}", platform_str, version);
+        let query = match ext_info::parse_extension_name(extension_id_str) {
+            Ok(parsed_name) => registry::query_first(registries, &parsed_name.publisher, &parsed_name.name)
+                .await
+                .map(|found| (parsed_name, found)),
+            Err(e) => Err(e),
+        };
+        let record = match query {
+            Ok((parsed_name, (registry, info))) => {
+                let mut record = output::InfoRecord::found(extension_id_str, registry.name(), &info);
+                if args.all_versions {
+                    match registry.versions(&parsed_name.publisher, &parsed_name.name).await {
+                        Ok(mut versions) => {
+                            versions.truncate(args.limit.unwrap_or(versions.len()));
+                            record.all_versions = versions;
+                        }
+                        Err(e) => {
+                            if args.output == cli::OutputFormat::Text {
+                                eprintln!("Failed to get versions of {}: {}", extension_id_str, e);
                             }
+                            record.error = Some(e.to_string());
+                            first_error.get_or_insert(e);
                         }
                     }
-                    Err(e) => {
-                        eprintln!("Failed to get info for {}: {}", extension_id_str, e);
-                        // Continue to the next extension if one fails in multi-mode
-                        if args.single.is_some() {
-                            return Err(e);
-                        }
+                }
+                record
+            }
+            Err(e) => {
+                if args.output == cli::OutputFormat::Text {
+                    eprintln!("Failed to get info for {}: {}", extension_id_str, e);
+                }
+                let record = output::InfoRecord::failed(extension_id_str, &e.to_string());
+                first_error.get_or_insert(e);
+                record
+            }
+        };
+        match args.output {
+            cli::OutputFormat::Text => {
+                if index > 0 { // Add a separator for multiple extensions
+                    println!("--------------------");
+                }
+                if record.status == output::Status::Ok {
+                    for line in record.text(&args.fields) {
+                        println!("{line}");
                     }
                 }
             }
+            cli::OutputFormat::Jsonl => println!("{}", record.json(&args.fields)),
+            cli::OutputFormat::Json => records.push(record.json(&args.fields)),
         }
     }
-    Ok(())
+    if args.output == cli::OutputFormat::Json {
+        println!("{}", serde_json::to_string_pretty(&records)?);
+    }
+
+    // A single extension that could not be found is an error,
+    // failures in a list are reported and the rest continues
+    match first_error {
+        Some(e) if args.single.is_some() => Err(Box::new(e)),
+        _ => Ok(()),
+    }
 }
 
+// Exit codes of download besides 0, and 1 for errors before any extension is downloaded
+const EXIT_PARTIAL_FAILURE: i32 = 3;
+const EXIT_TOTAL_FAILURE: i32 = 4;
+
+// Returns the exit code: 0 if every extension is in the destination,
+// EXIT_PARTIAL_FAILURE if some failed and EXIT_TOTAL_FAILURE if all did
 async fn handle_download_command(
     args: cli::DownloadArgs,
-    proxy: Option<&str>,
     verbose: bool,
-) -> Result<(), Box<dyn Error>> {
+    registries: &[Box<dyn Registry>],
+) -> Result<i32, Box<dyn Error>> {
     let extensions_to_download: Vec<String>;
 
     if let Some(single_extension_id) = &args.single {
@@ -130,40 +304,343 @@ async fn handle_download_command(
                 return Err(Box::new(e) as Box<dyn Error>);
             }
         };
-        let extensions_data: json::Extensions = match serde_json::from_str(&file_content) {
+        let extensions_data = match json::parse_extensions(&file_content) {
             Ok(data) => data,
             Err(e) => {
                 eprintln!("Failed to parse file {}: {}", &args.input, e);
-                return Err(Box::new(e) as Box<dyn Error>);
+                return Err(Box::from(e));
             }
         };
         extensions_to_download = extensions_data.recommendations;
     }
+    let extensions_to_download: Vec<String> = extensions_to_download
+        .into_iter()
+        .filter(|extension_id| {
+            let excluded = args
+                .exclude
+                .iter()
+                .any(|exclude| exclude.eq_ignore_ascii_case(extension_id));
+            if excluded && verbose {
+                println!("Skipping excluded extension: {}", extension_id);
+            }
+            !excluded
+        })
+        .collect();
+
+    if extensions_to_download.len() > 1 {
+        registry::prefetch(registries, &extensions_to_download).await;
+    }
+
+    let options = file::DownloadOptions {
+        force: args.force,
+        verbose,
+        os_arch: args.arch.as_deref(),
+        retries: args.retries,
+        verify_signature: args.verify_signature,
+        registries,
+    };
+    if args.dry_run {
+        return handle_dry_run(&extensions_to_download, &args.destination, &options).await;
+    }
 
     directory::create_dir_all(&args.destination)?;
 
+    let started = Instant::now();
+    let mut records = Vec::new();
+    let mut failed = false;
     for extension_id_str in extensions_to_download {
+        if failed && args.fail_fast {
+            records.push(output::DownloadRecord::new(
+                &extension_id_str,
+                output::Outcome::NotAttempted,
+            ));
+            continue;
+        }
+        let extension_started = Instant::now();
         if verbose {
             println!(
                 "Processing extension for download: {}",
                 &extension_id_str
             );
         }
-        if let Err(e) = file::download(
-            &extension_id_str,
-            &args.destination,
-            args.force,
-            proxy,   // Use passed proxy
-            verbose, // Use passed verbose
-            args.arch.as_deref(),
-        )
-        .await
-        {
+        match file::download(&extension_id_str, &args.destination, &options).await {
+            Ok(artifact) => {
+                if !artifact.skipped {
+                    println!(
+                        "Downloaded {} from {}",
+                        artifact.file_path, artifact.registry
+                    );
+                }
+                let mut record = output::DownloadRecord::artifact(
+                    &extension_id_str,
+                    &artifact,
+                    extension_started.elapsed(),
+                );
+                if args.report.is_some() {
+                    record.sha256 =
+                        checksum::sha256_file(std::path::Path::new(&artifact.file_path)).ok();
+                }
+                records.push(record);
+            }
+            Err(e) => {
+                eprintln!(
+                    "Error occurred when downloading {}: {}",
+                    extension_id_str, e
+                );
+                records.push(output::DownloadRecord::failed(
+                    &extension_id_str,
+                    &e.to_string(),
+                    extension_started.elapsed(),
+                ));
+                failed = true;
+            }
+        }
+    }
+
+    // Failing to write SHA256SUMS or the report still prints the summary and
+    // writes whatever else can be written, then fails the run
+    let mut write_error = None;
+    match checksum::write_sums(&args.destination, args.checksum_files) {
+        Ok(count) => {
+            if verbose {
+                println!(
+                    "Wrote checksums of {count} files to {}/{}",
+                    &args.destination,
+                    checksum::SUMS_FILE
+                );
+            }
+        }
+        Err(e) => {
             eprintln!(
-                "Error occurred when downloading {}: {}",
-                extension_id_str, e
+                "Failed to write {}/{}: {}",
+                &args.destination,
+                checksum::SUMS_FILE,
+                e
             );
+            write_error = Some(e);
+        }
+    }
+    let elapsed = started.elapsed();
+    for line in output::download_summary(&records, elapsed) {
+        println!("{line}");
+    }
+    if let Some(report) = &args.report {
+        match report::write(report, args.report_format, &records, elapsed) {
+            Ok(()) => {
+                if verbose {
+                    println!("Wrote report to {report}");
+                }
+            }
+            Err(e) => {
+                eprintln!("Failed to write report {}: {}", report, e);
+                write_error.get_or_insert(e);
+            }
+        }
+    }
+
+    let totals = output::Totals::of(&records);
+    if !totals.incomplete() {
+        write_error.map_or(Ok(0), Err)
+    } else if totals.nothing_available() {
+        Ok(EXIT_TOTAL_FAILURE)
+    } else {
+        Ok(EXIT_PARTIAL_FAILURE)
+    }
+}
+
+// download --dry-run: resolve every extension and print the plan.
+// Returns the exit code of download for the extensions that cannot be resolved.
+async fn handle_dry_run(
+    extensions: &[String],
+    destination: &str,
+    options: &file::DownloadOptions<'_>,
+) -> Result<i32, Box<dyn Error>> {
+    let mut records = Vec::new();
+    let mut failed = 0;
+    for extension_id_str in extensions {
+        let plan = match file::plan(extension_id_str, destination, options).await {
+            Ok(plan) => plan,
+            Err(e) => {
+                eprintln!("Cannot download {}: {}", extension_id_str, e);
+                failed += 1;
+                continue;
+            }
+        };
+        let size = match plan.action {
+            file::Action::Skip => None,
+            _ => plan.resolution.registry.size(&plan.download_url).await,
+        };
+        let record = output::PlanRecord {
+            id: extension_id_str.clone(),
+            action: plan.action,
+            version: plan.resolution.version.clone(),
+            platform: plan
+                .resolution
+                .target_platform
+                .clone()
+                .unwrap_or("universal".to_string()),
+            registry: plan.resolution.registry.name().to_string(),
+            url: plan.download_url,
+            file_path: plan.file_path,
+            size,
+        };
+        for line in record.text() {
+            println!("{line}");
+        }
+        records.push(record);
+    }
+    println!("{}", output::plan_summary(&records, failed));
+
+    if failed == 0 {
+        Ok(0)
+    } else if records.is_empty() {
+        Ok(EXIT_TOTAL_FAILURE)
+    } else {
+        Ok(EXIT_PARTIAL_FAILURE)
+    }
+}
+
+async fn handle_search_command(
+    args: cli::SearchArgs,
+    verbose: bool,
+    client: &client::HttpClient,
+    gallery: &url::Gallery,
+) -> Result<(), Box<dyn Error>> {
+    let sort_by = match args.sort {
+        cli::SearchSort::Relevance => search::SORT_RELEVANCE,
+        cli::SearchSort::Installs => search::SORT_INSTALLS,
+        cli::SearchSort::Rating => search::SORT_RATING,
+        cli::SearchSort::Updated => search::SORT_UPDATED,
+    };
+    let query = search::Query {
+        text: &args.text,
+        page: args.page,
+        page_size: args.page_size,
+        sort_by,
+        category: args.category.as_deref(),
+        tags: &args.tag,
+    };
+    let results = match search::search(client, &gallery.query_url(), &query, verbose).await {
+        Ok(results) => results,
+        Err(e) => {
+            eprintln!("Failed to search for {}: {}", args.text, e);
+            return Err(e);
+        }
+    };
+
+    match args.output {
+        cli::OutputFormat::Text => {
+            if results.results.is_empty() {
+                println!("No extensions found.");
+            } else {
+                for line in output::search_table(&results.results) {
+                    println!("{line}");
+                }
+                let first = (args.page as u64 - 1) * args.page_size as u64 + 1;
+                println!(
+                    "Showing {}-{} of {} results",
+                    first,
+                    first + results.results.len() as u64 - 1,
+                    results.total
+                );
+            }
+        }
+        cli::OutputFormat::Json => println!("{}", serde_json::to_string_pretty(&results)?),
+        cli::OutputFormat::Jsonl => {
+            for result in &results.results {
+                println!("{}", serde_json::to_string(result)?);
+            }
+        }
+    }
+
+    if args.add.is_empty() {
+        return Ok(());
+    }
+    // Only IDs from the results, so a typo does not end up in extensions.json
+    let mut ids = Vec::new();
+    for id in &args.add {
+        match results.results.iter().find(|result| result.id.eq_ignore_ascii_case(id)) {
+            Some(result) => ids.push(result.id.clone()),
+            None => {
+                eprintln!("{} is not in the search results", id);
+                return Err(Box::from(format!("{id} is not in the search results")));
+            }
+        }
+    }
+    let content = match fs::read_to_string(&args.input) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => "{}\n".to_string(),
+        Err(e) => {
+            eprintln!("Failed to read file {}: {}", &args.input, e);
+            return Err(Box::new(e) as Box<dyn Error>);
+        }
+    };
+    let (content, added) = match json::add_recommendations(&content, &ids) {
+        Ok(updated) => updated,
+        Err(e) => {
+            eprintln!("Failed to update {}: {}", &args.input, e);
+            return Err(Box::from(e));
+        }
+    };
+    if let Some(parent) = std::path::Path::new(&args.input).parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+    fs::write(&args.input, content)?;
+    if args.output == cli::OutputFormat::Text {
+        for id in &ids {
+            if added.contains(id) {
+                println!("Added {} to {}", id, &args.input);
+            } else {
+                println!("{} is already recommended in {}", id, &args.input);
+            }
+        }
+    }
+    Ok(())
+}
+
+fn handle_export_command(args: cli::ExportArgs, verbose: bool) -> Result<(), Box<dyn Error>> {
+    let root = extensions::local::root_path(&args.root)?;
+    let packages = extensions::local::scan(&root, verbose)?;
+    directory::create_dir_all(&args.output)?;
+    let count = match args.format {
+        cli::ExportFormat::OpenvsxStatic => export::openvsx_static(
+            &packages,
+            std::path::Path::new(&args.output),
+            &args.base_url,
+            verbose,
+        )?,
+    };
+    println!("Exported {count} extensions to {}", args.output);
+    Ok(())
+}
+
+fn handle_verify_command(args: cli::VerifyArgs, verbose: bool) -> Result<(), Box<dyn Error>> {
+    let report = checksum::verify(&args.destination)?;
+    if verbose {
+        for file_name in &report.ok {
+            println!("OK: {file_name}");
         }
     }
+    for file_name in &report.missing {
+        println!("MISSING: {file_name}");
+    }
+    for file_name in &report.extra {
+        println!("EXTRA: {file_name}");
+    }
+    for file_name in &report.corrupted {
+        println!("CORRUPTED: {file_name}");
+    }
+    println!(
+        "{} ok, {} missing, {} extra, {} corrupted",
+        report.ok.len(),
+        report.missing.len(),
+        report.extra.len(),
+        report.corrupted.len()
+    );
+    if !report.is_ok() {
+        return Err(Box::from("Verification against SHA256SUMS failed"));
+    }
     Ok(())
 }
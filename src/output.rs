@@ -0,0 +1,744 @@
+use crate::cli::InfoField;
+use crate::extensions::file::{Action, Artifact};
+use crate::extensions::info::{ExtensionInfo, Metadata, VersionInfo};
+use crate::extensions::search::SearchResult;
+use clap::ValueEnum;
+use serde::{Serialize, Serializer};
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+const UNIVERSAL: &str = "universal";
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    Ok,
+    Error,
+}
+
+// The result of `info` for one extension, printed as text or JSON
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct InfoRecord {
+    pub id: String,
+    pub status: Status,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub registry: Option<String>,
+    #[serde(flatten)]
+    pub metadata: Metadata,
+    // Latest version per target platform, "universal" for platform independent builds
+    pub versions: BTreeMap<String, String>,
+    // Every version, newest first (info --all-versions)
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub all_versions: Vec<VersionInfo>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl InfoRecord {
+    pub fn found(id: &str, registry: &str, info: &ExtensionInfo) -> InfoRecord {
+        let versions = info
+            .arch_versions
+            .iter()
+            .map(|(platform, version)| {
+                (
+                    platform.clone().unwrap_or(UNIVERSAL.to_string()),
+                    version.clone(),
+                )
+            })
+            .collect();
+        InfoRecord {
+            id: id.to_string(),
+            status: Status::Ok,
+            registry: Some(registry.to_string()),
+            metadata: info.metadata.clone(),
+            versions,
+            all_versions: Vec::new(),
+            error: None,
+        }
+    }
+
+    pub fn failed(id: &str, error: &str) -> InfoRecord {
+        InfoRecord {
+            id: id.to_string(),
+            status: Status::Error,
+            registry: None,
+            metadata: Metadata::default(),
+            versions: BTreeMap::new(),
+            all_versions: Vec::new(),
+            error: Some(error.to_string()),
+        }
+    }
+
+    // The record with only the selected fields (all if none are selected).
+    // id, status and error are always included.
+    pub fn json(&self, fields: &[InfoField]) -> Value {
+        let mut value = serde_json::to_value(self).unwrap_or_default();
+        if let Some(object) = value.as_object_mut() {
+            for field in InfoField::value_variants() {
+                if !selected(fields, *field) {
+                    for key in keys(*field) {
+                        object.remove(*key);
+                    }
+                }
+            }
+        }
+        value
+    }
+
+    // The human readable lines, errors are printed separately on stderr
+    pub fn text(&self, fields: &[InfoField]) -> Vec<String> {
+        let metadata = &self.metadata;
+        let mut lines = vec![format!("Extension: {}", self.id)];
+        let mut line = |field: InfoField, label: &str, value: Option<String>| {
+            if let Some(value) = value.filter(|_| selected(fields, field)) {
+                lines.push(format!("  {label}: {value}"));
+            }
+        };
+        let list = |values: &[String]| Some(values.join(", ")).filter(|s| !s.is_empty());
+        line(InfoField::Registry, "Registry", self.registry.clone());
+        line(InfoField::Name, "Name", metadata.display_name.clone());
+        line(
+            InfoField::Description,
+            "Description",
+            metadata.description.clone(),
+        );
+        let verified = match metadata.publisher_verified {
+            Some(true) => " (verified)",
+            _ => "",
+        };
+        line(
+            InfoField::Publisher,
+            "Publisher",
+            metadata
+                .publisher_display_name
+                .as_ref()
+                .map(|name| format!("{name}{verified}")),
+        );
+        line(
+            InfoField::Categories,
+            "Categories",
+            list(&metadata.categories),
+        );
+        line(InfoField::Tags, "Tags", list(&metadata.tags));
+        line(
+            InfoField::Installs,
+            "Installs",
+            metadata.install_count.map(|count| count.to_string()),
+        );
+        line(
+            InfoField::Rating,
+            "Rating",
+            metadata.rating.map(|rating| match metadata.rating_count {
+                Some(count) => format!("{rating:.1} ({count} ratings)"),
+                None => format!("{rating:.1}"),
+            }),
+        );
+        line(
+            InfoField::Updated,
+            "Last updated",
+            metadata.last_updated.clone(),
+        );
+        line(
+            InfoField::Repository,
+            "Repository",
+            metadata.repository.clone(),
+        );
+        line(InfoField::License, "License", metadata.license.clone());
+        if !selected(fields, InfoField::Versions) {
+            return lines;
+        }
+        if self.versions.is_empty() {
+            lines.push("  No version information found.".to_string());
+        } else {
+            lines.push("  Available versions per platform:".to_string());
+            for (platform, version) in &self.versions {
+                let platform = match platform.as_str() {
+                    UNIVERSAL => "Platform Independent",
+                    platform => platform,
+                };
+                lines.push(format!("    - {platform}: {version}"));
+            }
+        }
+        if !self.all_versions.is_empty() {
+            lines.push("  All versions:".to_string());
+            for version in &self.all_versions {
+                let mut line = format!(
+                    "    - {} ({})",
+                    version.version,
+                    version.platforms.join(", ")
+                );
+                if let Some(published) = &version.published {
+                    line.push_str(&format!(", published {published}"));
+                }
+                if let Some(engine) = &version.engine {
+                    line.push_str(&format!(", engine {engine}"));
+                }
+                if version.pre_release {
+                    line.push_str(", pre-release");
+                }
+                lines.push(line);
+            }
+        }
+        lines
+    }
+}
+
+fn selected(fields: &[InfoField], field: InfoField) -> bool {
+    fields.is_empty() || fields.contains(&field)
+}
+
+// Keys of the JSON record that belong to a field
+fn keys(field: InfoField) -> &'static [&'static str] {
+    match field {
+        InfoField::Registry => &["registry"],
+        InfoField::Name => &["display_name"],
+        InfoField::Description => &["description"],
+        InfoField::Publisher => &["publisher_display_name", "publisher_verified"],
+        InfoField::Categories => &["categories"],
+        InfoField::Tags => &["tags"],
+        InfoField::Installs => &["install_count"],
+        InfoField::Rating => &["rating", "rating_count"],
+        InfoField::Updated => &["last_updated"],
+        InfoField::Repository => &["repository"],
+        InfoField::License => &["license"],
+        InfoField::Versions => &["versions", "all_versions"],
+    }
+}
+
+// Search results as a table with aligned columns
+pub fn search_table(results: &[SearchResult]) -> Vec<String> {
+    let mut rows = vec![[
+        "ID".to_string(),
+        "VERSION".to_string(),
+        "INSTALLS".to_string(),
+        "RATING".to_string(),
+        "NAME".to_string(),
+    ]];
+    for result in results {
+        let metadata = &result.metadata;
+        rows.push([
+            result.id.clone(),
+            result.version.clone(),
+            metadata
+                .install_count
+                .map(|count| count.to_string())
+                .unwrap_or_default(),
+            metadata
+                .rating
+                .map(|rating| format!("{rating:.1}"))
+                .unwrap_or_default(),
+            metadata.display_name.clone().unwrap_or_default(),
+        ]);
+    }
+    let mut widths = [0; 5];
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    rows.iter()
+        .map(|row| {
+            let cells: Vec<String> = row
+                .iter()
+                .zip(widths)
+                .map(|(cell, width)| format!("{cell:<width$}"))
+                .collect();
+            cells.join("  ").trim_end().to_string()
+        })
+        .collect()
+}
+
+#[derive(Serialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum Outcome {
+    Downloaded,
+    // The file already existed
+    Skipped,
+    Failed,
+    // Not tried because an earlier extension failed (--fail-fast)
+    NotAttempted,
+}
+
+// The result of `download` for one extension, for the summary and the report
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct DownloadRecord {
+    // As requested in extensions.json or with --single
+    pub id: String,
+    pub outcome: Outcome,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    // Target platform of the package, "universal" for platform independent builds
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub platform: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub file_path: Option<String>,
+    // Size of the package, 0 if there is none
+    pub size: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+    // Registry the package came from
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub registry: Option<String>,
+    #[serde(rename = "duration_secs", serialize_with = "seconds")]
+    pub duration: Duration,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+fn seconds<S: Serializer>(duration: &Duration, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.serialize_f64(duration.as_secs_f64())
+}
+
+impl DownloadRecord {
+    pub fn new(id: &str, outcome: Outcome) -> DownloadRecord {
+        DownloadRecord {
+            id: id.to_string(),
+            outcome,
+            version: None,
+            platform: None,
+            file_path: None,
+            size: 0,
+            sha256: None,
+            registry: None,
+            duration: Duration::ZERO,
+            error: None,
+        }
+    }
+
+    pub fn artifact(id: &str, artifact: &Artifact, duration: Duration) -> DownloadRecord {
+        let outcome = if artifact.skipped {
+            Outcome::Skipped
+        } else {
+            Outcome::Downloaded
+        };
+        DownloadRecord {
+            version: Some(artifact.version.clone()),
+            platform: Some(
+                artifact
+                    .target_platform
+                    .clone()
+                    .unwrap_or(UNIVERSAL.to_string()),
+            ),
+            file_path: Some(artifact.file_path.clone()),
+            size: artifact.size,
+            registry: Some(artifact.registry.clone()),
+            duration,
+            ..DownloadRecord::new(id, outcome)
+        }
+    }
+
+    pub fn failed(id: &str, error: &str, duration: Duration) -> DownloadRecord {
+        DownloadRecord {
+            duration,
+            error: Some(error.to_string()),
+            ..DownloadRecord::new(id, Outcome::Failed)
+        }
+    }
+}
+
+// Totals of a download run
+#[derive(Serialize, Debug, Clone, Copy, PartialEq, Default)]
+pub struct Totals {
+    pub downloaded: usize,
+    pub skipped: usize,
+    pub failed: usize,
+    pub not_attempted: usize,
+    // Bytes of the downloaded packages
+    pub bytes: u64,
+}
+
+impl Totals {
+    pub fn of(records: &[DownloadRecord]) -> Totals {
+        let mut totals = Totals::default();
+        for record in records {
+            match record.outcome {
+                Outcome::Downloaded => {
+                    totals.downloaded += 1;
+                    totals.bytes += record.size;
+                }
+                Outcome::Skipped => totals.skipped += 1,
+                Outcome::Failed => totals.failed += 1,
+                Outcome::NotAttempted => totals.not_attempted += 1,
+            }
+        }
+        totals
+    }
+
+    // Some extensions were not downloaded
+    pub fn incomplete(&self) -> bool {
+        self.failed + self.not_attempted > 0
+    }
+
+    // Not a single extension is available in the destination
+    pub fn nothing_available(&self) -> bool {
+        self.downloaded + self.skipped == 0
+    }
+}
+
+pub fn human_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["KiB", "MiB", "GiB", "TiB"];
+    if bytes < 1024 {
+        return format!("{bytes} B");
+    }
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{value:.1} {}", UNITS[unit])
+}
+
+// The summary printed at the end of `download`, with the reason of each failure
+pub fn download_summary(records: &[DownloadRecord], elapsed: Duration) -> Vec<String> {
+    let totals = Totals::of(records);
+    let mut lines = vec![
+        "Summary:".to_string(),
+        format!(
+            "  Downloaded:      {} ({})",
+            totals.downloaded,
+            human_bytes(totals.bytes)
+        ),
+        format!("  Already exists:  {}", totals.skipped),
+        format!("  Failed:          {}", totals.failed),
+    ];
+    for record in records {
+        if let (Outcome::Failed, Some(error)) = (record.outcome, &record.error) {
+            lines.push(format!("    - {}: {error}", record.id));
+        }
+    }
+    if totals.not_attempted > 0 {
+        lines.push(format!("  Not attempted:   {}", totals.not_attempted));
+    }
+    lines.push(format!("  Elapsed:         {:.1}s", elapsed.as_secs_f64()));
+    lines
+}
+
+// What `download --dry-run` would do with one extension
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlanRecord {
+    pub id: String,
+    pub action: Action,
+    pub version: String,
+    // Target platform, "universal" for platform independent builds
+    pub platform: String,
+    pub registry: String,
+    pub url: String,
+    pub file_path: String,
+    // Size of the package, if the registry reports it without downloading
+    pub size: Option<u64>,
+}
+
+impl PlanRecord {
+    pub fn text(&self) -> Vec<String> {
+        let package = format!("{} {} ({})", self.id, self.version, self.platform);
+        if self.action == Action::Skip {
+            return vec![
+                format!("Would skip {package}, already exists"),
+                format!("  File: {}", self.file_path),
+            ];
+        }
+        let verb = match self.action {
+            Action::Replace => "replace",
+            _ => "download",
+        };
+        let size = self.size.map_or("size unknown".to_string(), human_bytes);
+        vec![
+            format!("Would {verb} {package} from {}", self.registry),
+            format!("  URL:  {}", self.url),
+            format!("  File: {} ({size})", self.file_path),
+        ]
+    }
+}
+
+// The last line of `download --dry-run`
+pub fn plan_summary(records: &[PlanRecord], failed: usize) -> String {
+    let count = |action| {
+        records
+            .iter()
+            .filter(|record| record.action == action)
+            .count()
+    };
+    let transfers: Vec<&PlanRecord> = records
+        .iter()
+        .filter(|record| record.action != Action::Skip)
+        .collect();
+    let bytes: u64 = transfers.iter().filter_map(|record| record.size).sum();
+    let unknown = transfers
+        .iter()
+        .filter(|record| record.size.is_none())
+        .count();
+    let mut summary = format!(
+        "Plan: {} to download, {} to replace, {} already exist, {failed} failed, {} to transfer",
+        count(Action::Download),
+        count(Action::Replace),
+        count(Action::Skip),
+        human_bytes(bytes)
+    );
+    if unknown > 0 {
+        summary.push_str(&format!(" (size unknown for {unknown})"));
+    }
+    summary
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    #[test]
+    fn test_info_record() {
+        let info = ExtensionInfo {
+            arch_versions: HashMap::from([
+                (None, "1.0.0".to_string()),
+                (Some("linux-x64".to_string()), "1.1.0".to_string()),
+            ]),
+            ..Default::default()
+        };
+        let record = InfoRecord::found("publisher.name", "msft", &info);
+        assert_eq!(
+            serde_json::to_value(&record).unwrap(),
+            serde_json::json!({
+                "id": "publisher.name",
+                "status": "ok",
+                "registry": "msft",
+                "versions": {"linux-x64": "1.1.0", "universal": "1.0.0"},
+            })
+        );
+        assert_eq!(
+            record.text(&[]),
+            vec![
+                "Extension: publisher.name",
+                "  Registry: msft",
+                "  Available versions per platform:",
+                "    - linux-x64: 1.1.0",
+                "    - Platform Independent: 1.0.0",
+            ]
+        );
+
+        let info = ExtensionInfo {
+            metadata: Metadata {
+                display_name: Some("Name".to_string()),
+                publisher_display_name: Some("Publisher".to_string()),
+                publisher_verified: Some(true),
+                tags: vec!["a".to_string(), "b".to_string()],
+                rating: Some(4.25),
+                rating_count: Some(8),
+                ..Default::default()
+            },
+            ..info
+        };
+        let record = InfoRecord::found("publisher.name", "msft", &info);
+        assert_eq!(
+            record.text(&[]),
+            vec![
+                "Extension: publisher.name",
+                "  Registry: msft",
+                "  Name: Name",
+                "  Publisher: Publisher (verified)",
+                "  Tags: a, b",
+                "  Rating: 4.2 (8 ratings)",
+                "  Available versions per platform:",
+                "    - linux-x64: 1.1.0",
+                "    - Platform Independent: 1.0.0",
+            ]
+        );
+        let fields = [InfoField::Publisher, InfoField::Rating];
+        assert_eq!(
+            record.text(&fields),
+            vec![
+                "Extension: publisher.name",
+                "  Publisher: Publisher (verified)",
+                "  Rating: 4.2 (8 ratings)",
+            ]
+        );
+        assert_eq!(
+            record.json(&fields),
+            serde_json::json!({
+                "id": "publisher.name",
+                "status": "ok",
+                "publisher_display_name": "Publisher",
+                "publisher_verified": true,
+                "rating": 4.25,
+                "rating_count": 8,
+            })
+        );
+
+        let record = InfoRecord {
+            all_versions: vec![
+                VersionInfo {
+                    version: "1.1.0".to_string(),
+                    platforms: vec!["linux-x64".to_string(), "universal".to_string()],
+                    published: Some("2024-05-02".to_string()),
+                    pre_release: true,
+                    engine: Some("^1.80.0".to_string()),
+                },
+                VersionInfo {
+                    version: "1.0.0".to_string(),
+                    platforms: vec!["universal".to_string()],
+                    ..Default::default()
+                },
+            ],
+            ..record
+        };
+        assert_eq!(
+            record.text(&[InfoField::Versions])[4..],
+            [
+                "  All versions:",
+                "    - 1.1.0 (linux-x64, universal), published 2024-05-02, engine ^1.80.0, pre-release",
+                "    - 1.0.0 (universal)",
+            ]
+        );
+        assert_eq!(record.json(&[])["all_versions"][0]["pre_release"], true);
+
+        let record = InfoRecord::failed("publisher.missing", "Not found");
+        assert_eq!(
+            serde_json::to_string(&record).unwrap(),
+            r#"{"id":"publisher.missing","status":"error","versions":{},"error":"Not found"}"#
+        );
+    }
+
+    #[test]
+    fn test_download_summary() {
+        let record = |id: &str, outcome, size, error: Option<&str>| DownloadRecord {
+            size,
+            error: error.map(|e| e.to_string()),
+            ..DownloadRecord::new(id, outcome)
+        };
+        let records = vec![
+            record("a.one", Outcome::Downloaded, 1536, None),
+            record("a.two", Outcome::Downloaded, 3 * 1024 * 1024, None),
+            record("b.skipped", Outcome::Skipped, 100, None),
+            record("c.failed", Outcome::Failed, 0, Some("Not found")),
+            record("d.later", Outcome::NotAttempted, 0, None),
+        ];
+        assert_eq!(
+            download_summary(&records, Duration::from_millis(2345)),
+            vec![
+                "Summary:",
+                "  Downloaded:      2 (3.0 MiB)",
+                "  Already exists:  1",
+                "  Failed:          1",
+                "    - c.failed: Not found",
+                "  Not attempted:   1",
+                "  Elapsed:         2.3s",
+            ]
+        );
+        let totals = Totals::of(&records);
+        assert!(totals.incomplete());
+        assert!(!totals.nothing_available());
+        assert!(Totals::of(&records[3..]).nothing_available());
+        assert!(!Totals::of(&records[..3]).incomplete());
+        assert_eq!(human_bytes(512), "512 B");
+        assert_eq!(human_bytes(1536), "1.5 KiB");
+    }
+
+    #[test]
+    fn test_download_record() {
+        let artifact = Artifact {
+            file_path: "out/publisher.name-1.0.0.vsix".to_string(),
+            version: "1.0.0".to_string(),
+            target_platform: None,
+            registry: "msft".to_string(),
+            size: 42,
+            skipped: true,
+        };
+        let record =
+            DownloadRecord::artifact("Publisher.Name", &artifact, Duration::from_millis(1500));
+        assert_eq!(
+            serde_json::to_value(&record).unwrap(),
+            serde_json::json!({
+                "id": "Publisher.Name",
+                "outcome": "skipped",
+                "version": "1.0.0",
+                "platform": "universal",
+                "file_path": "out/publisher.name-1.0.0.vsix",
+                "size": 42,
+                "registry": "msft",
+                "duration_secs": 1.5,
+            })
+        );
+        let record = DownloadRecord::failed("a.b", "Not found", Duration::ZERO);
+        assert_eq!(
+            serde_json::to_string(&record).unwrap(),
+            r#"{"id":"a.b","outcome":"failed","size":0,"duration_secs":0.0,"error":"Not found"}"#
+        );
+    }
+
+    #[test]
+    fn test_plan_record() {
+        let record = PlanRecord {
+            id: "publisher.name".to_string(),
+            action: Action::Download,
+            version: "1.0.0".to_string(),
+            platform: "linux-x64".to_string(),
+            registry: "msft".to_string(),
+            url: "https://example.com/name.vsix".to_string(),
+            file_path: "out/publisher.name-1.0.0@linux-x64.vsix".to_string(),
+            size: Some(2048),
+        };
+        assert_eq!(
+            record.text(),
+            vec![
+                "Would download publisher.name 1.0.0 (linux-x64) from msft",
+                "  URL:  https://example.com/name.vsix",
+                "  File: out/publisher.name-1.0.0@linux-x64.vsix (2.0 KiB)",
+            ]
+        );
+        let replace = PlanRecord {
+            action: Action::Replace,
+            size: None,
+            ..record.clone()
+        };
+        assert_eq!(
+            replace.text()[0],
+            "Would replace publisher.name 1.0.0 (linux-x64) from msft"
+        );
+        assert!(replace.text()[2].ends_with("(size unknown)"));
+        let skip = PlanRecord {
+            action: Action::Skip,
+            ..record.clone()
+        };
+        assert_eq!(
+            skip.text(),
+            vec![
+                "Would skip publisher.name 1.0.0 (linux-x64), already exists",
+                "  File: out/publisher.name-1.0.0@linux-x64.vsix",
+            ]
+        );
+        assert_eq!(
+            plan_summary(&[record, replace, skip], 1),
+            "Plan: 1 to download, 1 to replace, 1 already exist, 1 failed, 2.0 KiB to transfer (size unknown for 1)"
+        );
+    }
+
+    #[test]
+    fn test_search_table() {
+        let results = vec![
+            SearchResult {
+                id: "redhat.vscode-yaml".to_string(),
+                version: "1.14.0".to_string(),
+                metadata: Metadata {
+                    display_name: Some("YAML".to_string()),
+                    install_count: Some(21000000),
+                    rating: Some(4.25),
+                    ..Default::default()
+                },
+            },
+            SearchResult {
+                id: "a.b".to_string(),
+                version: "0.1.0".to_string(),
+                metadata: Metadata::default(),
+            },
+        ];
+        assert_eq!(
+            search_table(&results),
+            vec![
+                "ID                  VERSION  INSTALLS  RATING  NAME",
+                "redhat.vscode-yaml  1.14.0   21000000  4.2     YAML",
+                "a.b                 0.1.0",
+            ]
+        );
+    }
+}
@@ -0,0 +1,197 @@
+use crate::cli::ReportFormat;
+use crate::output::{DownloadRecord, Outcome, Totals};
+use serde::Serialize;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+const SUITE_NAME: &str = "download";
+
+#[derive(Serialize)]
+struct JsonReport<'a> {
+    #[serde(flatten)]
+    totals: Totals,
+    duration_secs: f64,
+    extensions: &'a [DownloadRecord],
+}
+
+pub fn json(records: &[DownloadRecord], elapsed: Duration) -> Result<String, serde_json::Error> {
+    let report = JsonReport {
+        totals: Totals::of(records),
+        duration_secs: elapsed.as_secs_f64(),
+        extensions: records,
+    };
+    serde_json::to_string_pretty(&report)
+}
+
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+// What happened to a package that is in the destination, as test output
+fn details(record: &DownloadRecord) -> String {
+    let mut lines = Vec::new();
+    let mut line = |label: &str, value: Option<&String>| {
+        if let Some(value) = value {
+            lines.push(format!("{label}: {value}"));
+        }
+    };
+    line("version", record.version.as_ref());
+    line("platform", record.platform.as_ref());
+    line("registry", record.registry.as_ref());
+    line("file", record.file_path.as_ref());
+    line("sha256", record.sha256.as_ref());
+    lines.push(format!("size: {}", record.size));
+    lines.join("\n")
+}
+
+// One test case per extension: failures are failed tests, extensions not
+// attempted after --fail-fast are skipped tests, the rest pass
+pub fn junit(records: &[DownloadRecord], elapsed: Duration) -> String {
+    let totals = Totals::of(records);
+    let attributes = format!(
+        r#"tests="{}" failures="{}" errors="0" skipped="{}" time="{:.3}""#,
+        records.len(),
+        totals.failed,
+        totals.not_attempted,
+        elapsed.as_secs_f64()
+    );
+    let mut xml = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n");
+    xml.push_str(&format!(
+        "<testsuites name=\"vsixHarvester\" {attributes}>\n"
+    ));
+    xml.push_str(&format!(
+        "  <testsuite name=\"{SUITE_NAME}\" {attributes}>\n"
+    ));
+    for record in records {
+        xml.push_str(&format!(
+            "    <testcase classname=\"{SUITE_NAME}\" name=\"{}\" time=\"{:.3}\">\n",
+            escape(&record.id),
+            record.duration.as_secs_f64()
+        ));
+        match record.outcome {
+            Outcome::Failed => {
+                let error = escape(record.error.as_deref().unwrap_or_default());
+                xml.push_str(&format!(
+                    "      <failure message=\"{error}\">{error}</failure>\n"
+                ));
+            }
+            Outcome::NotAttempted => {
+                xml.push_str(
+                    "      <skipped message=\"Not attempted after an earlier failure\"/>\n",
+                );
+            }
+            Outcome::Downloaded | Outcome::Skipped => {
+                xml.push_str(&format!(
+                    "      <system-out>{}</system-out>\n",
+                    escape(&details(record))
+                ));
+            }
+        }
+        xml.push_str("    </testcase>\n");
+    }
+    xml.push_str("  </testsuite>\n</testsuites>\n");
+    xml
+}
+
+pub fn write(
+    path: &str,
+    format: ReportFormat,
+    records: &[DownloadRecord],
+    elapsed: Duration,
+) -> Result<(), Box<dyn Error>> {
+    let content = match format {
+        ReportFormat::Json => json(records, elapsed)?,
+        ReportFormat::Junit => junit(records, elapsed),
+    };
+    if let Some(parent) = Path::new(path).parent() {
+        if !parent.as_os_str().is_empty() {
+            fs::create_dir_all(parent)?;
+        }
+    }
+    fs::write(path, content)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn records() -> Vec<DownloadRecord> {
+        vec![
+            DownloadRecord {
+                version: Some("1.0.0".to_string()),
+                platform: Some("linux-x64".to_string()),
+                file_path: Some("out/a.one-1.0.0@linux-x64.vsix".to_string()),
+                size: 10,
+                sha256: Some("abc".to_string()),
+                registry: Some("msft".to_string()),
+                duration: Duration::from_millis(250),
+                ..DownloadRecord::new("a.one", Outcome::Downloaded)
+            },
+            DownloadRecord::failed(
+                "b.<two>",
+                "No registry provides \"b.<two>\"",
+                Duration::ZERO,
+            ),
+            DownloadRecord::new("c.three", Outcome::NotAttempted),
+        ]
+    }
+
+    #[test]
+    fn test_json() {
+        let report: serde_json::Value =
+            serde_json::from_str(&json(&records(), Duration::from_secs(2)).unwrap()).unwrap();
+        assert_eq!(report["downloaded"], 1);
+        assert_eq!(report["failed"], 1);
+        assert_eq!(report["not_attempted"], 1);
+        assert_eq!(report["bytes"], 10);
+        assert_eq!(report["duration_secs"], 2.0);
+        assert_eq!(report["extensions"][0]["sha256"], "abc");
+        assert_eq!(report["extensions"][1]["outcome"], "failed");
+        assert_eq!(report["extensions"][2]["outcome"], "not_attempted");
+    }
+
+    #[test]
+    fn test_junit() {
+        let xml = junit(&records(), Duration::from_secs(2));
+        let document = roxmltree::Document::parse(&xml).unwrap();
+        let suite = document
+            .descendants()
+            .find(|node| node.has_tag_name("testsuite"))
+            .unwrap();
+        assert_eq!(suite.attribute("tests"), Some("3"));
+        assert_eq!(suite.attribute("failures"), Some("1"));
+        assert_eq!(suite.attribute("skipped"), Some("1"));
+        let cases: Vec<_> = suite
+            .children()
+            .filter(|node| node.has_tag_name("testcase"))
+            .collect();
+        assert_eq!(cases[0].attribute("time"), Some("0.250"));
+        let out = cases[0].first_element_child().unwrap();
+        assert!(out.text().unwrap().contains("sha256: abc"));
+        assert_eq!(cases[1].attribute("name"), Some("b.<two>"));
+        let failure = cases[1].first_element_child().unwrap();
+        assert_eq!(failure.tag_name().name(), "failure");
+        assert_eq!(
+            failure.attribute("message"),
+            Some("No registry provides \"b.<two>\"")
+        );
+        assert!(cases[2]
+            .first_element_child()
+            .unwrap()
+            .has_tag_name("skipped"));
+    }
+}
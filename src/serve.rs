@@ -0,0 +1,664 @@
+use crate::extensions::local;
+use crate::extensions::local::LocalPackage;
+use crate::extensions::signature;
+use crate::extensions::version;
+use crate::extensions::vsix;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{header, Body, Method, Request, Response, Server, StatusCode};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashSet};
+use std::convert::Infallible;
+use std::error::Error;
+use std::fs;
+use std::net::ToSocketAddrs;
+use std::path::Path;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+pub const VSIX_ASSET_TYPE: &str = "Microsoft.VisualStudio.Services.VSIXPackage";
+pub const MANIFEST_ASSET_TYPE: &str = "Microsoft.VisualStudio.Code.Manifest";
+// Path prefix of the public marketplace, accepted so serviceUrl can keep the same shape
+const GALLERY_PREFIX: &str = "/_apis/public/gallery";
+const FILTER_EXTENSION_ID: u64 = 4;
+const FILTER_EXTENSION_NAME: u64 = 7;
+const FILTER_TARGET: u64 = 8;
+const FILTER_SEARCH_TEXT: u64 = 10;
+const FILTER_EXCLUDE_WITH_FLAGS: u64 = 12;
+const FLAG_INCLUDE_LATEST_VERSION_ONLY: u64 = 0x200;
+const DEFAULT_PAGE_SIZE: u64 = 50;
+const UNIVERSAL: &str = "universal";
+
+#[derive(Debug)]
+pub struct Reply {
+    pub status: StatusCode,
+    pub content_type: &'static str,
+    pub body: Vec<u8>,
+}
+
+impl Reply {
+    fn json(value: Value) -> Reply {
+        Reply {
+            status: StatusCode::OK,
+            content_type: "application/json",
+            body: value.to_string().into_bytes(),
+        }
+    }
+
+    fn text(status: StatusCode, message: &str) -> Reply {
+        Reply {
+            status,
+            content_type: "text/plain",
+            body: message.as_bytes().to_vec(),
+        }
+    }
+
+    fn file(path: &Path) -> Reply {
+        match fs::read(path) {
+            Ok(body) => Reply {
+                status: StatusCode::OK,
+                content_type: "application/octet-stream",
+                body,
+            },
+            Err(_) => Reply::text(StatusCode::NOT_FOUND, "Not found"),
+        }
+    }
+}
+
+// Stable GUID-like id derived from the name, the local index has no real ids
+fn id(name: &str) -> String {
+    let hash = format!("{:x}", Sha256::digest(name.to_lowercase().as_bytes()));
+    format!(
+        "{}-{}-{}-{}-{}",
+        &hash[0..8],
+        &hash[8..12],
+        &hash[12..16],
+        &hash[16..20],
+        &hash[20..32]
+    )
+}
+
+fn full_name(package: &LocalPackage) -> String {
+    format!("{}.{}", package.manifest.publisher, package.manifest.name)
+}
+
+fn asset_uri(base_url: &str, package: &LocalPackage) -> String {
+    let manifest = &package.manifest;
+    format!(
+        "{base_url}/assets/{}/{}/{}/{}",
+        manifest.publisher,
+        manifest.name,
+        manifest.version,
+        manifest.target_platform.as_deref().unwrap_or(UNIVERSAL)
+    )
+}
+
+// UTC time in the format of the marketplace, e.g. 2024-05-01T12:00:00.000Z
+fn timestamp(time: SystemTime) -> String {
+    let seconds = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+    // Civil date of the day since 1970-01-01, in eras of 400 years from March 0000
+    let days = seconds / 86400 + 719_468;
+    let (era, day_of_era) = (days / 146_097, days % 146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * month_from_march + 2) / 5 + 1;
+    let month = (month_from_march + 2) % 12 + 1;
+    let year = era * 400 + year_of_era + u64::from(month <= 2);
+    format!(
+        "{year:04}-{month:02}-{day:02}T{:02}:{:02}:{:02}.000Z",
+        seconds % 86400 / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+// When the package file was last written, as its publish date
+fn modified(package: &LocalPackage) -> Option<SystemTime> {
+    fs::metadata(&package.path).and_then(|m| m.modified()).ok()
+}
+
+fn version_json(base_url: &str, package: &LocalPackage) -> Value {
+    let asset_uri = asset_uri(base_url, package);
+    let mut asset_types = vec![VSIX_ASSET_TYPE, MANIFEST_ASSET_TYPE];
+    if local::signature_path(package).is_file() {
+        asset_types.push(signature::ASSET_TYPE);
+    }
+    let files: Vec<Value> = asset_types
+        .iter()
+        .map(|asset_type| {
+            json!({
+                "assetType": asset_type,
+                "source": format!("{asset_uri}/{asset_type}"),
+            })
+        })
+        .collect();
+    // VS Code checks the engine for compatibility and picks pre-releases by the flag
+    let manifest = &package.manifest;
+    let mut properties = Vec::new();
+    if let Some(engine) = &manifest.engine {
+        properties.push(json!({"key": vsix::ENGINE_PROPERTY, "value": engine}));
+    }
+    if manifest.pre_release {
+        properties.push(json!({"key": vsix::PRE_RELEASE_PROPERTY, "value": "true"}));
+    }
+    let mut version = json!({
+        "version": manifest.version,
+        "assetUri": asset_uri,
+        "fallbackAssetUri": asset_uri,
+        "files": files,
+        "properties": properties,
+    });
+    if let Some(platform) = &manifest.target_platform {
+        version["targetPlatform"] = json!(platform);
+    }
+    if let Some(time) = modified(package) {
+        version["lastUpdated"] = json!(timestamp(time));
+    }
+    version
+}
+
+// With latest_only, only the newest version of each target platform is listed
+fn extension_json(base_url: &str, versions: &[&LocalPackage], latest_only: bool) -> Value {
+    let manifest = &versions[0].manifest;
+    let mut platforms = HashSet::new();
+    let versions_json: Vec<Value> = versions
+        .iter()
+        .filter(|package| {
+            !latest_only || platforms.insert(package.manifest.target_platform.clone())
+        })
+        .map(|package| version_json(base_url, package))
+        .collect();
+    let mut extension = json!({
+        "publisher": {
+            "publisherId": id(&manifest.publisher),
+            "publisherName": manifest.publisher,
+            "displayName": manifest.publisher,
+        },
+        "extensionId": id(&full_name(versions[0])),
+        "extensionName": manifest.name,
+        "displayName": manifest.name,
+        "flags": "validated, public",
+        "versions": versions_json,
+        "statistics": [],
+    });
+    if let Some(time) = versions
+        .iter()
+        .filter_map(|package| modified(package))
+        .max()
+    {
+        extension["lastUpdated"] = json!(timestamp(time));
+    }
+    extension
+}
+
+// Packages grouped per extension, newest version first as the marketplace does
+fn extensions(packages: &[LocalPackage]) -> Vec<Vec<&LocalPackage>> {
+    let mut groups: BTreeMap<String, Vec<&LocalPackage>> = BTreeMap::new();
+    for package in packages {
+        groups
+            .entry(full_name(package).to_lowercase())
+            .or_default()
+            .push(package);
+    }
+    groups
+        .into_values()
+        .map(|mut versions| {
+            versions.sort_by(|a, b| version::compare(&b.manifest.version, &a.manifest.version));
+            versions
+        })
+        .collect()
+}
+
+// Criteria of one type are alternatives, criteria of different types must all
+// match. Other criteria (tags, categories, ...) are not known for local
+// packages, so a query with one of them matches nothing.
+fn matches(versions: &[&LocalPackage], criteria: &[(u64, String)]) -> bool {
+    let name = full_name(versions[0]).to_lowercase();
+    let mut by_type: BTreeMap<u64, Vec<String>> = BTreeMap::new();
+    for (filter_type, value) in criteria {
+        by_type
+            .entry(*filter_type)
+            .or_default()
+            .push(value.to_lowercase());
+    }
+    by_type
+        .iter()
+        .all(|(filter_type, values)| match *filter_type {
+            FILTER_EXTENSION_ID => values.contains(&id(&name)),
+            FILTER_EXTENSION_NAME => values.contains(&name),
+            FILTER_SEARCH_TEXT => values.iter().any(|value| name.contains(value.as_str())),
+            // Every package of a local index is a published VS Code extension
+            FILTER_TARGET | FILTER_EXCLUDE_WITH_FLAGS => true,
+            _ => false,
+        })
+}
+
+// Answer an extensionquery request in the shape of the marketplace response
+pub fn query(packages: &[LocalPackage], request: &Value, base_url: &str) -> Value {
+    let extensions = extensions(packages);
+    let latest_only =
+        request["flags"].as_u64().unwrap_or(0) & FLAG_INCLUDE_LATEST_VERSION_ONLY != 0;
+    let mut filters = request["filters"].as_array().cloned().unwrap_or_default();
+    if filters.is_empty() {
+        filters.push(json!({}));
+    }
+    let results: Vec<Value> = filters
+        .iter()
+        .map(|filter| {
+            let criteria: Vec<(u64, String)> = filter["criteria"]
+                .as_array()
+                .map(|criteria| {
+                    criteria
+                        .iter()
+                        .filter_map(|criterion| {
+                            Some((
+                                criterion["filterType"].as_u64()?,
+                                criterion["value"].as_str().unwrap_or_default().to_string(),
+                            ))
+                        })
+                        .collect()
+                })
+                .unwrap_or_default();
+            let matched: Vec<&Vec<&LocalPackage>> = extensions
+                .iter()
+                .filter(|versions| matches(versions, &criteria))
+                .collect();
+            let page_size = filter["pageSize"].as_u64().unwrap_or(DEFAULT_PAGE_SIZE);
+            let page_number = filter["pageNumber"].as_u64().unwrap_or(1).max(1);
+            let page: Vec<Value> = matched
+                .iter()
+                .skip(((page_number - 1) * page_size) as usize)
+                .take(page_size as usize)
+                .map(|versions| extension_json(base_url, versions, latest_only))
+                .collect();
+            json!({
+                "extensions": page,
+                "pagingToken": null,
+                "resultMetadata": [{
+                    "metadataType": "ResultCount",
+                    "metadataItems": [{"name": "TotalCount", "count": matched.len()}],
+                }],
+            })
+        })
+        .collect();
+    json!({ "results": results })
+}
+
+fn asset(
+    packages: &[LocalPackage],
+    publisher: &str,
+    extension_name: &str,
+    version: &str,
+    platform: &str,
+    asset_type: &str,
+) -> Reply {
+    let target_platform = Some(platform).filter(|platform| *platform != UNIVERSAL);
+    let Some(package) = local::find(
+        packages,
+        publisher,
+        extension_name,
+        version,
+        target_platform,
+    ) else {
+        return Reply::text(StatusCode::NOT_FOUND, "Extension not found");
+    };
+    match asset_type {
+        VSIX_ASSET_TYPE => Reply::file(&package.path),
+        signature::ASSET_TYPE => Reply::file(&local::signature_path(package)),
+        MANIFEST_ASSET_TYPE => match fs::read(&package.path)
+            .map_err(Box::from)
+            .and_then(|content| vsix::read_entry(&content, vsix::PACKAGE_JSON_ENTRY))
+        {
+            Ok(body) => Reply {
+                status: StatusCode::OK,
+                content_type: "application/json",
+                body,
+            },
+            Err(e) => Reply::text(StatusCode::NOT_FOUND, &e.to_string()),
+        },
+        _ => Reply::text(StatusCode::NOT_FOUND, "Unknown asset type"),
+    }
+}
+
+pub fn route(
+    packages: &[LocalPackage],
+    method: &Method,
+    path: &str,
+    query_string: Option<&str>,
+    base_url: &str,
+    body: &[u8],
+) -> Reply {
+    let path = path.strip_prefix(GALLERY_PREFIX).unwrap_or(path);
+    let segments: Vec<&str> = path.trim_matches('/').split('/').collect();
+    let get = method == Method::GET || method == Method::HEAD;
+    match segments.as_slice() {
+        ["extensionquery"] if method == Method::POST => {
+            match serde_json::from_slice::<Value>(body) {
+                Ok(request) => Reply::json(query(packages, &request, base_url)),
+                Err(e) => Reply::text(StatusCode::BAD_REQUEST, &format!("Invalid query: {e}")),
+            }
+        }
+        ["publishers", publisher, "vsextensions", extension_name, version, "vspackage"] if get => {
+            let platform = query_string
+                .and_then(|query| {
+                    query
+                        .split('&')
+                        .find_map(|pair| pair.strip_prefix("targetPlatform="))
+                })
+                .unwrap_or(UNIVERSAL);
+            asset(
+                packages,
+                publisher,
+                extension_name,
+                version,
+                platform,
+                VSIX_ASSET_TYPE,
+            )
+        }
+        ["assets", publisher, extension_name, version, platform, asset_type] if get => asset(
+            packages,
+            publisher,
+            extension_name,
+            version,
+            platform,
+            asset_type,
+        ),
+        _ => Reply::text(StatusCode::NOT_FOUND, "Not found"),
+    }
+}
+
+async fn handle(
+    packages: Arc<Vec<LocalPackage>>,
+    request: Request<Body>,
+    verbose: bool,
+) -> Result<Response<Body>, Infallible> {
+    let (parts, body) = request.into_parts();
+    let host = parts
+        .headers
+        .get(header::HOST)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("localhost");
+    let base_url = format!("http://{host}");
+    let body = hyper::body::to_bytes(body).await.unwrap_or_default();
+    let reply = route(
+        &packages,
+        &parts.method,
+        parts.uri.path(),
+        parts.uri.query(),
+        &base_url,
+        &body,
+    );
+    if verbose {
+        println!("{} {} {}", parts.method, parts.uri, reply.status.as_u16());
+    }
+    Ok(Response::builder()
+        .status(reply.status)
+        .header(header::CONTENT_TYPE, reply.content_type)
+        .body(Body::from(reply.body))
+        .unwrap_or_default())
+}
+
+// Serve the packages in the directory until the process is stopped.
+// The directory is indexed once at startup.
+pub async fn run(root: &str, host: &str, port: u16, verbose: bool) -> Result<(), Box<dyn Error>> {
+    let root = local::root_path(root)?;
+    let packages = Arc::new(local::scan(&root, verbose)?);
+    let address = (host, port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| format!("Cannot resolve {host}"))?;
+    let server = Server::try_bind(&address)?;
+    println!(
+        "Serving {} packages from {} on http://{address}",
+        packages.len(),
+        root.display()
+    );
+    let make_service = make_service_fn(move |_| {
+        let packages = packages.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request| {
+                handle(packages.clone(), request, verbose)
+            }))
+        }
+    });
+    server.serve(make_service).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extensions::parse::{parse, parse_versions};
+    use crate::extensions::vsix::tests::vsix;
+    use crate::extensions::vsix::Manifest;
+    use std::time::Duration;
+
+    const BASE_URL: &str = "http://gallery.example.com";
+
+    // The packages are scanned from the returned directory, keep it alive
+    fn index() -> (tempfile::TempDir, Vec<LocalPackage>) {
+        let temp = tempfile::tempdir().unwrap();
+        let dir = temp.path();
+        for (file_name, version, platform) in [
+            ("a-1.0.0.vsix", "1.0.0", None),
+            ("a-1.1.0.vsix", "1.1.0", None),
+            ("a-1.1.0@linux-x64.vsix", "1.1.0", Some("linux-x64")),
+        ] {
+            fs::write(
+                dir.join(file_name),
+                vsix("publisher", "name", version, platform),
+            )
+            .unwrap();
+        }
+        fs::write(dir.join("b.vsix"), vsix("other", "tool", "2.0.0", None)).unwrap();
+        let packages = local::scan(dir, false).unwrap();
+        (temp, packages)
+    }
+
+    fn post_query(packages: &[LocalPackage], request: Value) -> Value {
+        let reply = route(
+            packages,
+            &Method::POST,
+            "/_apis/public/gallery/extensionquery",
+            None,
+            BASE_URL,
+            request.to_string().as_bytes(),
+        );
+        assert_eq!(reply.status, StatusCode::OK);
+        serde_json::from_slice(&reply.body).unwrap()
+    }
+
+    #[test]
+    fn test_query_by_name() {
+        let (_dir, packages) = index();
+        let response = post_query(
+            &packages,
+            json!({
+                "filters": [{"criteria": [{"filterType": 7, "value": "Publisher.Name"}]}],
+                "flags": 914
+            }),
+        );
+        let extensions = response["results"][0]["extensions"].as_array().unwrap();
+        assert_eq!(extensions.len(), 1);
+        let versions = extensions[0]["versions"].as_array().unwrap();
+        assert_eq!(versions[0]["version"], "1.1.0");
+
+        let extension_info = parse(&response).unwrap();
+        assert_eq!(
+            extension_info.arch_versions.get(&None).map(|s| s.as_str()),
+            Some("1.1.0")
+        );
+        assert_eq!(
+            extension_info
+                .arch_versions
+                .get(&Some("linux-x64".to_string()))
+                .map(|s| s.as_str()),
+            Some("1.1.0")
+        );
+    }
+
+    #[test]
+    fn test_query_by_id() {
+        let (_dir, packages) = index();
+        let request = |flags: u64| {
+            json!({
+                "filters": [{"criteria": [
+                    {"filterType": 8, "value": "Microsoft.VisualStudio.Code"},
+                    {"filterType": 4, "value": id("publisher.name")},
+                ]}],
+                "flags": flags
+            })
+        };
+        let response = post_query(&packages, request(0));
+        let extensions = response["results"][0]["extensions"].as_array().unwrap();
+        assert_eq!(extensions.len(), 1);
+        assert_eq!(extensions[0]["extensionName"], "name");
+        assert_eq!(extensions[0]["versions"].as_array().unwrap().len(), 3);
+
+        // Only the newest version of each platform
+        let response = post_query(&packages, request(FLAG_INCLUDE_LATEST_VERSION_ONLY));
+        let versions = response["results"][0]["extensions"][0]["versions"]
+            .as_array()
+            .unwrap();
+        assert_eq!(versions.len(), 2);
+        assert!(versions.iter().all(|version| version["version"] == "1.1.0"));
+
+        // Criteria a local index cannot answer match nothing
+        let response = post_query(
+            &packages,
+            json!({"filters": [{"criteria": [{"filterType": 5, "value": "Themes"}]}]}),
+        );
+        assert_eq!(response["results"][0]["extensions"], json!([]));
+    }
+
+    #[test]
+    fn test_query_criteria() {
+        let (_dir, packages) = index();
+        let names = |criteria: Value| -> Vec<String> {
+            let response = post_query(&packages, json!({"filters": [{"criteria": criteria}]}));
+            response["results"][0]["extensions"]
+                .as_array()
+                .unwrap()
+                .iter()
+                .map(|extension| extension["extensionName"].as_str().unwrap().to_string())
+                .collect()
+        };
+        let by_name = |value: &str| json!({"filterType": 7, "value": value});
+        let themes = json!({"filterType": 5, "value": "Themes"});
+
+        // Alternatives of the same type
+        assert_eq!(
+            names(json!([by_name("publisher.name"), by_name("other.tool")])),
+            vec!["tool", "name"]
+        );
+        // Every type has to match
+        assert!(names(json!([
+            by_name("publisher.name"),
+            {"filterType": 10, "value": "tool"}
+        ]))
+        .is_empty());
+        // An unsupported criterion rejects the packages wherever it is
+        assert!(names(json!([by_name("publisher.name"), themes.clone()])).is_empty());
+        assert!(names(json!([themes, by_name("publisher.name")])).is_empty());
+    }
+
+    #[test]
+    fn test_version_properties() {
+        let temp = tempfile::tempdir().unwrap();
+        let path = temp.path().join("publisher.name-2.0.0.vsix");
+        let file = fs::File::create(&path).unwrap();
+        let published = UNIX_EPOCH + Duration::from_secs(1_714_564_800);
+        file.set_modified(published).unwrap();
+        let package = LocalPackage {
+            manifest: Manifest {
+                publisher: "publisher".to_string(),
+                name: "name".to_string(),
+                version: "2.0.0".to_string(),
+                engine: Some("^1.80.0".to_string()),
+                pre_release: true,
+                ..Default::default()
+            },
+            path,
+        };
+        let extension = extension_json(BASE_URL, &[&package], false);
+        assert_eq!(extension["lastUpdated"], "2024-05-01T12:00:00.000Z");
+        let response = json!({"results": [{"extensions": [extension]}]});
+        let versions = parse_versions(&response).unwrap();
+        assert_eq!(versions[0].engine.as_deref(), Some("^1.80.0"));
+        assert!(versions[0].pre_release);
+        assert_eq!(
+            versions[0].published.as_deref(),
+            Some("2024-05-01T12:00:00.000Z")
+        );
+        assert_eq!(
+            timestamp(UNIX_EPOCH + Duration::from_secs(951_868_799)),
+            "2000-02-29T23:59:59.000Z"
+        );
+    }
+
+    #[test]
+    fn test_query_search_and_paging() {
+        let (_dir, packages) = index();
+        let all = post_query(
+            &packages,
+            json!({"filters": [{"pageSize": 1, "pageNumber": 2}]}),
+        );
+        assert_eq!(
+            all["results"][0]["resultMetadata"][0]["metadataItems"][0]["count"],
+            2
+        );
+        assert_eq!(all["results"][0]["extensions"][0]["extensionName"], "name");
+        let search = post_query(
+            &packages,
+            json!({"filters": [{"criteria": [{"filterType": 10, "value": "TOOL"}]}]}),
+        );
+        let extensions = search["results"][0]["extensions"].as_array().unwrap();
+        assert_eq!(extensions.len(), 1);
+        assert_eq!(extensions[0]["extensionName"], "tool");
+    }
+
+    #[test]
+    fn test_assets() {
+        let (_dir, packages) = index();
+        let vspackage = route(
+            &packages,
+            &Method::GET,
+            "/publishers/publisher/vsextensions/name/1.1.0/vspackage",
+            Some("targetPlatform=linux-x64"),
+            BASE_URL,
+            b"",
+        );
+        assert_eq!(vspackage.status, StatusCode::OK);
+        assert_eq!(
+            vsix::read_manifest(&vspackage.body)
+                .unwrap()
+                .target_platform,
+            Some("linux-x64".to_string())
+        );
+
+        let manifest = route(
+            &packages,
+            &Method::GET,
+            &format!("/assets/publisher/name/1.0.0/universal/{MANIFEST_ASSET_TYPE}"),
+            None,
+            BASE_URL,
+            b"",
+        );
+        assert_eq!(manifest.status, StatusCode::OK);
+        assert_eq!(manifest.body, b"{}");
+
+        for path in [
+            "/publishers/publisher/vsextensions/name/9.9.9/vspackage".to_string(),
+            format!(
+                "/assets/publisher/name/1.0.0/universal/{}",
+                signature::ASSET_TYPE
+            ),
+            "/unknown".to_string(),
+        ] {
+            let reply = route(&packages, &Method::GET, &path, None, BASE_URL, b"");
+            assert_eq!(reply.status, StatusCode::NOT_FOUND);
+        }
+    }
+}